@@ -0,0 +1,211 @@
+use std::path::{Path, PathBuf};
+use std::fmt::Write;
+use syn::visit::{self, Visit};
+use syn::spanned::Spanned;
+use quote::ToTokens;
+
+// Writes `fn_index.rs` into OUT_DIR: the (line, column) range and qualified name of every function
+// and closure in the crate, which `failure::caller` uses to name the function a `Location` points into,
+// and the text of every source file for the snippets in error reports.
+
+const SRC: &str = "src";
+
+// One row of `FN_INDEX`: where an item starts and ends, as (line, column), and its qualified name.
+struct Item {file: String, start: (u32, u32), end: (u32, u32), name: String}
+
+struct Indexer {file: String, scope: Vec<String>, items: Vec<Item>}
+
+fn main() {
+
+    let root = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").expect("build - CARGO_MANIFEST_DIR not set"));
+    let out = PathBuf::from(std::env::var("OUT_DIR").expect("build - OUT_DIR not set"));
+
+    println!("cargo:rerun-if-changed={}", SRC);
+
+    let mut files = Vec::new();
+
+    collect(&root.join(SRC), &mut files);
+
+    files.sort();
+
+    let mut items = Vec::new();
+    let mut sources = String::from("pub const SOURCES: &[(&str, &str)] = &[\n");
+
+    for file in files {
+
+        let rel = file.strip_prefix(&root).unwrap_or(&file).to_string_lossy().replace('\\', "/");
+
+        let text = std::fs::read_to_string(&file).unwrap_or_else(|err| panic!("build - Failed to read {}: {}", rel, err));
+
+        // A file that doesn't parse fails the compile anyway, rustc gives the better message.
+        let Ok(ast) = syn::parse_file(&text) else {continue;};
+
+        writeln!(sources, "    ({:?}, include_str!({:?})),", rel, file.to_string_lossy()).unwrap();
+
+        let mut indexer = Indexer {file: rel.clone(), scope: module_path(&rel), items: Vec::new()};
+
+        indexer.visit_file(&ast);
+
+        items.extend(indexer.items);
+
+    }
+
+    let mut table = String::from("pub const FN_INDEX: &[(&str, (u32, u32), (u32, u32), &str)] = &[\n");
+
+    for Item {file, start, end, name} in items {
+
+        writeln!(table, "    ({:?}, {:?}, {:?}, {:?}),", file, start, end, name).unwrap();
+
+    }
+
+    table += "];\n";
+    table += &sources;
+    table += "];\n";
+
+    std::fs::write(out.join("fn_index.rs"), table).expect("build - Failed to write fn_index.rs");
+
+}
+
+fn collect(dir: &Path, files: &mut Vec<PathBuf>) {
+
+    let Ok(entries) = std::fs::read_dir(dir) else {return;};
+
+    for entry in entries.flatten() {
+
+        let path = entry.path();
+
+        if path.is_dir() {collect(&path, files);}
+
+        else if path.extension().is_some_and(|ext| ext == "rs") {files.push(path);}
+
+    }
+
+}
+
+// `src/main.rs` and `src/lib.rs` are the crate root, `src/a/b.rs` and `src/a/b/mod.rs` are `a::b`.
+fn module_path(rel: &str) -> Vec<String> {
+
+    let rel = rel.strip_prefix(SRC).unwrap_or(rel).trim_start_matches('/').trim_end_matches(".rs");
+
+    let mut parts: Vec<String> = rel.split('/').map(String::from).collect();
+
+    if parts.last().is_some_and(|last| last == "mod") {parts.pop();}
+
+    if parts.len() == 1 && (parts[0] == "main" || parts[0] == "lib") {parts.clear();}
+
+    parts
+
+}
+
+fn type_name(ty: &syn::Type) -> String {
+
+    if let syn::Type::Path(path) = ty {
+
+        if let Some(last) = path.path.segments.last() {return last.ident.to_string();}
+
+    }
+
+    ty.to_token_stream().to_string().replace(' ', "")
+
+}
+
+impl Indexer {
+
+    fn record(&mut self, span: proc_macro2::Span, name: String) {
+
+        let mut path = self.scope.clone();
+
+        path.push(name);
+
+        // proc-macro2 columns start at 0, `Location::column` at 1
+        let (start, end) = (span.start(), span.end());
+
+        self.items.push(Item {
+            file: self.file.clone(),
+            start: (start.line as u32, start.column as u32 + 1),
+            end: (end.line as u32, end.column as u32 + 1),
+            name: path.join("::"),
+        });
+
+    }
+
+    fn scoped(&mut self, name: String, inner: impl FnOnce(&mut Self)) {
+
+        self.scope.push(name);
+
+        inner(self);
+
+        self.scope.pop();
+
+    }
+
+}
+
+impl<'ast> Visit<'ast> for Indexer {
+
+    fn visit_item_mod(&mut self, node: &'ast syn::ItemMod) {
+
+        self.scoped(node.ident.to_string(), |this| visit::visit_item_mod(this, node));
+
+    }
+
+    fn visit_item_fn(&mut self, node: &'ast syn::ItemFn) {
+
+        self.record(node.span(), node.sig.ident.to_string());
+
+        self.scoped(node.sig.ident.to_string(), |this| visit::visit_item_fn(this, node));
+
+    }
+
+    // Trait impls are named the way rustc prints them, `<Type as Trait>`.
+    fn visit_item_impl(&mut self, node: &'ast syn::ItemImpl) {
+
+        let name = match &node.trait_ {
+
+            Some((_, path, _)) => format!("<{} as {}>", type_name(&node.self_ty), path.segments.last().map(|seg| seg.ident.to_string()).unwrap_or_default()),
+            None => type_name(&node.self_ty),
+
+        };
+
+        self.scoped(name, |this| visit::visit_item_impl(this, node));
+
+    }
+
+    fn visit_impl_item_fn(&mut self, node: &'ast syn::ImplItemFn) {
+
+        self.record(node.span(), node.sig.ident.to_string());
+
+        self.scoped(node.sig.ident.to_string(), |this| visit::visit_impl_item_fn(this, node));
+
+    }
+
+    fn visit_item_trait(&mut self, node: &'ast syn::ItemTrait) {
+
+        self.scoped(node.ident.to_string(), |this| visit::visit_item_trait(this, node));
+
+    }
+
+    fn visit_trait_item_fn(&mut self, node: &'ast syn::TraitItemFn) {
+
+        if node.default.is_some() {self.record(node.span(), node.sig.ident.to_string());}
+
+        self.scoped(node.sig.ident.to_string(), |this| visit::visit_trait_item_fn(this, node));
+
+    }
+
+    fn visit_expr_closure(&mut self, node: &'ast syn::ExprClosure) {
+
+        self.record(node.span(), "{{closure}}".into());
+
+        self.scoped("{{closure}}".into(), |this| visit::visit_expr_closure(this, node));
+
+    }
+
+    // Functions generated by `macro_rules!` report locations inside the macro body.
+    fn visit_item_macro(&mut self, node: &'ast syn::ItemMacro) {
+
+        if let Some(ident) = &node.ident {self.record(node.span(), format!("{}!", ident));}
+
+    }
+
+}
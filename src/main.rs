@@ -1,23 +1,39 @@
 mod tools;
 
 use tools::*;
+use std::time::Duration;
 
 const DEFAULT_PORT: u16 = 5000;
-const DEFAULT_TIMEOUT: u64 = 500;
+const DEFAULT_TIMEOUT: Duration = Duration::from_millis(500);
 
 fn main() {
 
     // if std::env::args().len() == 0 {return;}
 
-    // let outout = IOManager::new().unwrap_or_stderr();
+    // IOManager::new(".").install();
 
-    // let json = JSON::new().unwrap_or_stderr();
+    // install_panic_hook();
+
+    // select_reporter(&std::env::var("PROJECT_REPORTER").unwrap_or("log".into())).unwrap_or_stderr();
+
+    // let defaults = serde_json::json!({"network_args": {
+    //     "port": DEFAULT_PORT,
+    //     "timeout": DEFAULT_TIMEOUT.as_millis() as u64,
+    // }});
+
+    // let schema = Schema::object([Field::required("network_args", Schema::object([
+    //     Field::optional("ip", Schema::of::<std::net::IpAddr>()),
+    //     Field::required("port", Schema::integer(1, u16::MAX as i128)),
+    //     Field::required("timeout", Schema::of::<Duration>()),
+    // ])), Field::optional("redact", Schema::array(Schema::Str))]);
+
+    // let json = JSON::load(defaults, &schema).unwrap_or_stderr();
 
     // let connection = {
         
     //     let ip = json.get_or::<&str>(&["network_args", "ip"], "");
-    //     let port = json.get_or::<u16>(&["network_args", "port"], DEFAULT_PORT);
-    //     let timeout = json.get_or::<u64>(&["network_args", "timeout"], DEFAULT_TIMEOUT);
+    //     let port = json.get::<u16>(&["network_args", "port"]).unwrap_or_stderr();
+    //     let timeout = json.get::<Duration>(&["network_args", "timeout"]).unwrap_or_stderr();
 
     //     Connection::new(ip, port, timeout).unwrap_or_stderr()
 
@@ -25,7 +41,13 @@ fn main() {
 
     // format!("Connection established with: {}", connection.peer_addr().unwrap()).send_to_stdout();
 
-    std::thread::sleep(std::time::Duration::from_secs(20));
+    // if std::env::args().any(|arg| arg == "--dump-config") {
+
+    //     println!("{:#}", json.provenance());
+
+    // }
+
+    std::thread::sleep(Duration::from_secs(20));
 
     println!("Exiting...");
 
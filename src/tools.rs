@@ -1,8 +1,13 @@
 use core::panic;
 use std::io::Write;
-use std::cell::RefCell;
+use std::cell::Cell;
+use std::sync::Mutex;
 
-thread_local! {static LOGGER: RefCell<Option<IOManager>> = RefCell::new(None);}
+// The run log, shared by every thread so that rayon workers and the panic hook reach it too.
+static LOGGER: Mutex<Option<IOManager>> = Mutex::new(None);
+
+// Set while this thread holds LOGGER, so a panic inside the IOManager doesn't wait on itself.
+thread_local! {static LOGGING: Cell<bool> = Cell::new(false);}
 
 // -------------------------------------------- Traits -------------------------------------------- //
 #[allow(unused)]
@@ -20,547 +25,4596 @@ pub trait ExtString: AsRef<str> + Sized {
 
     fn send_to_stdout<'a>(self) -> Self {
 
-        return LOGGER.with(|mng| {
+        return with_logger(|mng| mng.push_stdout(self))
+            .unwrap_or_else(|| panic!("ExtString::send_to_stdout - No IOManager installed."));
+    
+    }
+
+    fn send_to_stderr<'a>(self) -> Self {
+        
+        return with_logger(|mng| mng.push_stderr(self))
+            .unwrap_or_else(|| panic!("ExtString::send_to_stderr - No IOManager installed."));
+
+    }
+
+}
+pub trait ExtResult<T> {fn unwrap_or_stderr(self) -> T;}
+pub trait MapOption<'a,T>: Sized {
+    
+    #[track_caller]
+    fn attempt(self, msg: impl Into<STR<'a>>) -> Attempt<'a,T>;
+
+}
+pub trait Context<'a,T>: Sized {
+
+    #[track_caller]
+    fn context(self, msg: impl Into<STR<'a>>) -> Attempt<'a,T>;
+
+    #[track_caller]
+    fn with_context<M: Into<STR<'a>>>(self, msg: impl FnOnce() -> M) -> Attempt<'a,T>;
+
+}
+pub trait Reporter: Send + Sync {fn emit(&self, fail: &Fail);}
+pub trait IntoOwned<T> {fn into_owned(self) -> Attempt<'static,T>;}
+pub trait Classify<'a,T> {
+
+    fn category(self, category: Category) -> Attempt<'a,T>;
+
+    fn code(self, code: &'static str) -> Attempt<'a,T>;
+
+}
+
+// -------------------------------------- Structures & Types -------------------------------------- //
+
+type STR<'a> = std::borrow::Cow<'a, str>;
+pub type Attempt<'a,T> = Result<T, Fail<'a>>;
+
+pub struct Fail<'a> {places: Vec<failure::ErrDetails<'a>>, msg: STR<'a>, cause: Option<Box<dyn std::error::Error + Send + Sync>>, category: Category, code: Option<STR<'static>>, time: u64, trace: Option<Box<failure::Trace>>}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)] #[serde(rename_all = "lowercase")] pub enum Category {Config, Io, Network, Parse, Python, Internal}
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)] pub struct Report {pub message: String, pub category: Category, pub code: Option<String>, pub timestamp: u64, pub chain: Vec<Frame>, #[serde(default)] pub backtrace: Vec<Frame>, #[serde(default)] pub cause: Option<Box<Report>>}
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)] pub struct Frame {pub file: String, pub line: u32, #[serde(default)] pub column: u32, pub function: String, pub context: Option<String>}
+pub struct JSON {root: serde_json::Value, sources: std::collections::BTreeMap<String, Source>, fallbacks: std::sync::Mutex<Vec<json_io::Fallback>>}
+#[derive(Clone, Debug)] pub enum Source {Default, File(std::path::PathBuf), Env(String), Argv, Stdin, Cli(String), Code}
+pub struct ByteSize(pub u64);
+pub struct ConfigWatch {state: std::sync::Arc<json_io::WatchState>, stop: Option<std::sync::mpsc::Sender<()>>, thread: Option<std::thread::JoinHandle<()>>}
+pub struct Change {pub path: String, pub old: Option<serde_json::Value>, pub new: Option<serde_json::Value>}
+pub struct RecordStream<R: std::io::BufRead> {reader: R, array: bool, started: bool, done: bool, index: usize}
+pub struct Record {index: usize, value: serde_json::Value}
+pub struct Field {name: String, required: bool, schema: Schema}
+
+pub enum Schema {
+    Any, Bool, Str,
+    Integer {min: Option<i128>, max: Option<i128>},
+    Number {min: Option<f64>, max: Option<f64>},
+    Enum(Vec<serde_json::Value>),
+    Array(Box<Schema>),
+    Object(Vec<Field>),
+    Check(&'static str, fn(&serde_json::Value) -> Option<String>),
+}
+pub struct LogReporter;
+pub struct TerminalReporter {color: bool}
+pub struct JsonReporter;
+pub struct Connection {stream: std::net::TcpStream}
+pub struct IOManager {stderr: std::fs::File, stdout: std::fs::File}
+
+// ------------------------------------------- Functions ------------------------------------------- //
+
+#[track_caller]
+pub fn fail<'a,T>(msg: impl Into<std::borrow::Cow<'a,str>>) -> Attempt<'a,T> {
+    
+    return Err(Fail::new(std::panic::Location::caller(), msg));
+
+}
+
+/// Runs `f` on the installed `IOManager`. None if there is none, or if this thread is already
+/// inside the IOManager (a panic raised while writing the log).
+pub(crate) fn with_logger<R>(f: impl FnOnce(&mut IOManager) -> R) -> Option<R> {
+
+    struct Busy;
+
+    impl Drop for Busy {fn drop(&mut self) {LOGGING.with(|busy| busy.set(false));}}
+
+    if LOGGING.with(Cell::get) {return None;}
+
+    let mut mng = LOGGER.lock().unwrap_or_else(|poison| poison.into_inner());
+
+    let mng = mng.as_mut()?;
+
+    LOGGING.with(|busy| busy.set(true));
+
+    let _busy = Busy;
+
+    return Some(f(mng));
+
+}
+
+pub use redaction::{redact, add_secret};
+pub use reporting::{set_reporter, select_reporter};
+pub use failure::{set_snippets, set_backtrace, without_backtrace, install_panic_hook};
+
+// -------------------------------------------- Modules -------------------------------------------- //
+
+mod extend_string {
+
+    use crate::tools::ExtString;
+
+    impl<T> ExtString for T where T: AsRef<str> {}
+
+}
+
+mod json_io {
+
+    use crate::tools::*;
+    use serde_json::Value;
+    use serde::{Deserializer, de::DeserializeOwned};
+    use preprocess::{preprocess, selected_profile, check_profile};
+
+    impl JSON {
+
+        /// Reads the launch payload from the command line (see `read_payload` for the accepted forms)
+        /// and checks it against `schema`.
+        pub fn new<'a>(schema: &Schema) -> Attempt<'a,Self> {
+
+            let (root, source) = read_payload()?.attempt(
+                "JSON::new - No JSON provided. Pass it as an argument, `-` for stdin, `@file`, `--config` or `--config-b64`."
+            ).category(Category::Config).code("config.missing")?;
+
+            let profile = selected_profile()?;
+
+            let (root, profiles) = preprocess(root, source.origin(), profile.as_deref())?;
+
+            return JSON::from_value(root, source).finish(profile.as_deref(), &profiles, schema);
+
+        }
+
+        // Shared end of `new`, `from_file` and `load`: the selected profile must exist, then the
+        // `--patch`/`--set` overrides apply and the result is checked against `schema`.
+        fn finish<'a>(mut self, profile: Option<&str>, profiles: &[String], schema: &Schema) -> Attempt<'a,Self> {
+
+            check_profile(profile, profiles)?;
+
+            // Secrets are known before overrides run, so a failing patch can't echo them
+            self.register_secrets();
+            self.apply_overrides()?;
+            self.register_secrets();
+            self.validate(schema)?;
+
+            return Ok(self);
+
+        }
+
+        pub fn from_value(root: Value, source: Source) -> Self {
+
+            let mut sources = std::collections::BTreeMap::new();
+
+            sources.insert(String::new(), source);
+
+            return JSON {root, sources, fallbacks: Default::default()};
+
+        }
+
+        /// Resolves `path` and converts the value found there. The path may be a JSON Pointer
+        /// (`/network_args/peers/2/ip`), a dotted path (`network_args.peers[2].ip`) or a key list.
+        pub fn get<'a,T>(&'a self, path: impl ToPath) -> Attempt<'a,T> where Value: Convert<'a,T> {
+
+            let path = path.to_path()?;
+
+            return path.resolve(&self.root).category(Category::Config)?.make().map_err(|mut err| {
+
+                // Conversion errors quote the value, short or numeric secrets aren't caught by `redact`
+                err.msg = match self.is_secret(&path) {
+
+                    true => format!("JSON::get - Value at '{}' is not a valid {} (not shown, the path is secret)", path, std::any::type_name::<T>()).into(),
+                    false => format!("{} at '{}'", err.msg, path).into(),
+
+                };
+
+                err.with_category(Category::Config)
+
+            });
+        
+        }
+
+        /// Like `get`, but falls back to `default`. Every fallback is recorded for `JSON::provenance`.
+        pub fn get_or<'a,T: std::fmt::Debug>(&'a self, path: impl ToPath, default: T) -> T where Value: Convert<'a,T> {
+
+            match without_backtrace(|| self.get(&path)) {
+
+                Ok(val) => val,
+
+                Err(err) => {
+
+                    let (path, default_shown) = match path.to_path() {
+
+                        Ok(path) if self.is_secret(&path) => (path.to_string(), "***".to_string()),
+                        Ok(path) => (path.to_string(), format!("{:?}", default)),
+                        Err(err) => (err.msg.to_string(), format!("{:?}", default)),
+
+                    };
+
+                    let fallback = Fallback {path, default: default_shown, reason: err.msg.to_string()};
+
+                    self.fallbacks.lock().unwrap_or_else(|poison| poison.into_inner()).push(fallback);
+
+                    default
+
+                }
+
+            }
+
+        }
+
+        pub fn find<'a>(&'a self, path: impl ToPath) -> Attempt<'a,&'a Value> {
+
+            return path.to_path()?.resolve(&self.root);
+
+        }
+
+        /// Deserializes the subtree at `path` into any `serde` type, e.g. a whole `network_args` struct.
+        pub fn get_as<'a,T: DeserializeOwned>(&'a self, path: impl ToPath) -> Attempt<'a,T> {
+
+            let path = path.to_path()?;
+
+            return deserialize(path.resolve(&self.root)?, path, |at| self.is_secret(at)).category(Category::Config);
+
+        }
+
+        /// Deserializes the whole document into a config struct.
+        pub fn into_config<'a,T: DeserializeOwned>(self) -> Attempt<'a,T> {
+
+            return deserialize(&self.root, Path::root(), |at| self.is_secret(at)).category(Category::Config);
+
+        }
+
+    }
+
+    // Serde errors only describe the failure, so the path inside the subtree is tracked
+    // separately and appended to the path the subtree was taken from. Serde quotes the offending
+    // value, so the error is left out where `secret` says the path is secret.
+    pub(crate) fn deserialize<'a,'de,T: DeserializeOwned>(de: impl Deserializer<'de, Error = serde_json::Error>, mut at: Path, secret: impl Fn(&Path) -> bool) -> Attempt<'a,T> {
+
+        use serde_path_to_error::Segment as Seg;
+
+        return serde_path_to_error::deserialize(de).or_else(|err| {
+
+            for seg in err.path().iter() {
+
+                match seg {
+
+                    Seg::Seq {index} => at.push(Segment::Index(*index)),
+                    Seg::Map {key} => at.push(Segment::Key(key.clone())),
+                    Seg::Enum {variant} => at.push(Segment::Key(variant.clone())),
+                    Seg::Unknown => break,
+
+                }
 
-            if let Some(mng) = mng.borrow_mut().as_mut() {
-                
-                return mng.push_stdout(self);
-            
             }
 
-            panic!("ExtString::send_to_stdout - No IOManager found in thread local storage.");
+            if secret(&at) {return fail(format!("JSON::get_as - Invalid value at '{}' (not shown, the path is secret)", at));}
+
+            fail(format!("JSON::get_as - {} at '{}'", err.inner(), at))
 
         });
-    
+
     }
 
-    fn send_to_stderr<'a>(self) -> Self {
+    impl std::ops::Deref for JSON {type Target = Value; fn deref(&self) -> &Value {&self.root}}
+
+    pub use path::{ToPath, Path, Segment};
+    pub use input::{read_payload, flag_values};
+    pub use watch::WatchState;
+
+    pub struct Fallback {path: String, default: String, reason: String}
+
+    pub trait Convert<'a,T> {fn make(&'a self) -> Attempt<'a,T>;}
+
+    impl<'a> Convert<'a,&'a str> for Value {
+        
+        fn make(&'a self) -> Attempt<'a,&str> {
         
-        return LOGGER.with(|mng| {
+            self.as_str().attempt(format!("json_io::Convert - Value is not a string: {:?}", self))
+    
+        }
+
+    }
+
+    // Integers are widened to i128 so every target type can be range checked the same way.
+    // Floats are only accepted when they hold a whole number.
+    fn as_integer(val: &Value) -> Option<i128> {
+
+        if let Some(x) = val.as_i64() {return Some(x as i128);}
+        if let Some(x) = val.as_u64() {return Some(x as i128);}
+
+        let x = val.as_f64()?;
+
+        if x.fract() != 0.0 || x.abs() >= 2f64.powi(127) {return None;}
+
+        return Some(x as i128);
+
+    }
+
+    // `exact` is the largest integer the float type represents without rounding (2^mantissa bits).
+    fn as_float(val: &Value, exact: i128, max: f64, name: &str, range: String) -> Attempt<'static,f64> {
+
+        let x = match val.as_f64() {
+
+            Some(x) => x,
+            None => return fail(format!("json_io::Convert - Value {} is not a number ({} expects {})", val, name, range)),
+
+        };
+
+        if x.abs() > max {return fail(format!("json_io::Convert - {} is out of range for {} (allowed {})", val, name, range));}
+
+        if let (true, Some(int)) = (val.is_i64() || val.is_u64(), as_integer(val)) {
+
+            if int.abs() > exact {
+
+                return fail(format!("json_io::Convert - {} cannot be represented exactly by {} (integers allowed {} to {})", val, name, -exact, exact));
+
+            }
+
+        }
+
+        return Ok(x);
+
+    }
+
+    macro_rules! convert_int {($($t:ty),*) => {$(
+
+        impl Convert<'_,$t> for Value {
+
+            fn make(&self) -> Attempt<'_,$t> {
+
+                let x = as_integer(self).attempt(format!(
+                    "json_io::Convert - Value {} is not an integer ({} expects {} to {})", self, stringify!($t), <$t>::MIN, <$t>::MAX
+                ))?;
+
+                return <$t>::try_from(x).ok().attempt(format!(
+                    "json_io::Convert - {} is out of range for {} (allowed {} to {})", self, stringify!($t), <$t>::MIN, <$t>::MAX
+                ));
 
-            if let Some(mng) = mng.borrow_mut().as_mut() {
-                
-                return mng.push_stdout(self);
-            
             }
 
-            panic!("ExtString::send_to_stdout - No IOManager found in thread local storage.");
+        }
+
+    )*}}
+
+    convert_int!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize);
+
+    impl Convert<'_,f64> for Value {
+
+        fn make(&self) -> Attempt<'_,f64> {
+
+            as_float(self, 1 << f64::MANTISSA_DIGITS, f64::MAX, "f64", format!("{:e} to {:e}", f64::MIN, f64::MAX))
+
+        }
+
+    }
+
+    impl Convert<'_,f32> for Value {
+
+        fn make(&self) -> Attempt<'_,f32> {
+
+            as_float(self, 1 << f32::MANTISSA_DIGITS, f32::MAX as f64, "f32", format!("{:e} to {:e}", f32::MIN, f32::MAX)).map(|x| x as f32)
+
+        }
+
+    }
+
+    impl Convert<'_,bool> for Value {
+
+        fn make(&self) -> Attempt<'_,bool> {
+
+            self.as_bool().attempt(format!("json_io::Convert - Value {} is not a boolean", self))
+
+        }
+
+    }
+
+    #[cfg(test)]
+    mod tests {
+
+        use super::*;
+        use serde_json::json;
+
+        // A document as if read from `project.json`, shared by the tests of the submodules.
+        pub(super) fn doc(root: Value) -> JSON {JSON::from_value(root, Source::File("project.json".into()))}
+
+        // The message of a conversion that has to fail.
+        fn rejected<T: std::fmt::Debug>(val: Value) -> String where for<'a> Value: Convert<'a,T> {
+
+            let result: Attempt<T> = val.make();
+
+            return result.map(|ok| panic!("{} converted to {:?}", val, ok)).unwrap_err().msg.to_string();
+
+        }
+
+        #[test]
+        fn integers_are_range_checked() {
+
+            let msg = rejected::<u16>(json!(70000));
+
+            assert!(msg.contains("70000 is out of range for u16 (allowed 0 to 65535)"), "{}", msg);
+
+            let msg = rejected::<u8>(json!(-1));
+
+            assert!(msg.contains("-1 is out of range for u8 (allowed 0 to 255)"), "{}", msg);
+
+            let msg = rejected::<i64>(json!(u64::MAX));
+
+            assert!(msg.contains("for i64"), "{}", msg);
+
+            let max: u64 = json!(u64::MAX).make().unwrap();
+
+            assert_eq!(max, u64::MAX);
+
+        }
+
+        #[test]
+        fn integers_need_whole_numbers() {
+
+            let msg = rejected::<i32>(json!(1.5));
+
+            assert!(msg.contains("1.5 is not an integer (i32 expects -2147483648 to 2147483647)"), "{}", msg);
+
+            rejected::<u8>(json!("1"));
+
+            let x: u16 = json!(3.0).make().unwrap();
+
+            assert_eq!(x, 3);
+
+        }
+
+        #[test]
+        fn floats_must_be_exact_and_in_range() {
+
+            let msg = rejected::<f64>(json!((1u64 << 53) + 1));
+
+            assert!(msg.contains("cannot be represented exactly by f64"), "{}", msg);
+
+            let x: f64 = json!(1u64 << 53).make().unwrap();
+
+            assert_eq!(x, 9007199254740992.0);
+
+            let msg = rejected::<f32>(json!(1e39));
+
+            assert!(msg.contains("out of range for f32"), "{}", msg);
+
+            let msg = rejected::<f32>(json!((1u64 << 24) + 1));
+
+            assert!(msg.contains("cannot be represented exactly by f32"), "{}", msg);
+
+            let x: f32 = json!(0.5).make().unwrap();
+
+            assert_eq!(x, 0.5);
+
+        }
+
+    }
+
+    mod input {
+
+        use crate::tools::*;
+        use serde_json::Value;
+        use base64::{Engine as _, alphabet, engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig}};
+        use std::io::Read;
+        use std::path::PathBuf;
+
+        // Flags that consume the following argument as their value.
+        const VALUE_FLAGS: [&str; 5] = ["--config", "--config-b64", "--set", "--patch", "--profile"];
+
+        const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+        const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+        #[cfg(feature = "compression")]
+        const MAX_DECOMPRESSED: u64 = 256 << 20;
+
+        const LENIENT: GeneralPurposeConfig = GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent);
+        const STANDARD_ANY: GeneralPurpose = GeneralPurpose::new(&alphabet::STANDARD, LENIENT);
+        const URL_SAFE_ANY: GeneralPurpose = GeneralPurpose::new(&alphabet::URL_SAFE, LENIENT);
+
+        enum Channel {Auto(String), Json(String), Base64(String), Stdin, File(PathBuf)}
+
+        /// Finds the launch payload on the command line. Accepted forms:
+        /// - a plain argument holding JSON or base64 encoded JSON (standard or URL-safe, padding optional),
+        /// - `-` to read it from stdin, `@path/to/file.json` to read it from a file,
+        /// - `--config <json>` or `--config-b64 <base64>`.
+        ///
+        /// With the `compression` feature any of these may be gzip or zstd compressed. Other `--flags` are ignored here.
+        /// Returns `None` when no payload was given.
+        pub fn read_payload<'a>() -> Attempt<'a,Option<(Value, Source)>> {
+
+            let channel = match find_channel(std::env::args().skip(1))? {
+
+                Some(channel) => channel,
+                None => return Ok(None),
+
+            };
+
+            let (bytes, source, forced) = match channel {
+
+                Channel::Auto(text) => (text.into_bytes(), Source::Argv, None),
+                Channel::Json(text) => (text.into_bytes(), Source::Argv, Some(false)),
+                Channel::Base64(text) => (text.into_bytes(), Source::Argv, Some(true)),
+
+                Channel::Stdin => {
+
+                    let mut bytes = Vec::new();
+
+                    std::io::stdin().read_to_end(&mut bytes)?;
+
+                    (bytes, Source::Stdin, None)
+
+                },
+
+                Channel::File(path) => match std::fs::read(&path) {
+
+                    Ok(bytes) => (bytes, Source::File(path), None),
+                    Err(err) => return fail(format!("JSON::read_payload - Failed to read {}: {}", path.display(), err)).category(Category::Io),
+
+                },
+
+            };
+
+            return Ok(Some((decode(bytes, forced)?, source)));
+
+        }
+
+        /// Every value given for `flag` (one of `VALUE_FLAGS`), in command line order.
+        pub fn flag_values<'a>(flag: &str) -> Attempt<'a,Vec<String>> {
+
+            let (_, flags) = split_args(std::env::args().skip(1))?;
+
+            return Ok(flags.into_iter().filter(|(name, _)| name == flag).map(|(_, value)| value).collect());
+
+        }
+
+        // Splits the command line into plain arguments and `(flag, value)` pairs for `VALUE_FLAGS`.
+        // Both `--flag value` and `--flag=value` are accepted, other `--flags` are dropped.
+        fn split_args<'a>(args: impl Iterator<Item = String>) -> Attempt<'a,(Vec<String>, Vec<(String, String)>)> {
+
+            let mut plain = Vec::new();
+            let mut flags = Vec::new();
+            let mut args = args.peekable();
+
+            while let Some(arg) = args.next() {
+
+                let (flag, inline) = match arg.split_once('=') {
+
+                    Some((flag, value)) if flag.starts_with("--") => (flag.to_string(), Some(value.to_string())),
+                    _ => (arg.clone(), None),
+
+                };
+
+                if VALUE_FLAGS.contains(&flag.as_str()) {
+
+                    let value = match inline {
+
+                        Some(value) => value,
+                        None => args.next().attempt(format!("JSON::read_payload - Missing value after {}", flag)).category(Category::Config)?,
+
+                    };
+
+                    flags.push((flag, value));
+
+                } else if !arg.starts_with("--") {
+
+                    plain.push(arg);
+
+                }
+
+            }
+
+            return Ok((plain, flags));
+
+        }
+
+        fn find_channel<'a>(args: impl Iterator<Item = String>) -> Attempt<'a,Option<Channel>> {
+
+            let (plain, flags) = split_args(args)?;
+
+            let from_flags = flags.into_iter().filter_map(|(flag, value)| match flag.as_str() {
+
+                "--config" => Some(Channel::Json(value)),
+                "--config-b64" => Some(Channel::Base64(value)),
+                _ => None,
+
+            });
+
+            let from_plain = plain.into_iter().map(|arg| match arg.as_str() {
+
+                "-" => Channel::Stdin,
+                _ if arg.starts_with('@') => Channel::File(PathBuf::from(&arg[1..])),
+                _ => Channel::Auto(arg),
+
+            });
+
+            let mut channels = from_flags.chain(from_plain);
+
+            let found = channels.next();
+
+            if channels.next().is_some() {return fail("JSON::read_payload - More than one payload given on the command line.").category(Category::Config);}
+
+            return Ok(found);
+
+        }
+
+        // `forced` is Some(true) for base64 only, Some(false) for JSON only and None to pick by content:
+        // text starting with `{` or `[` is JSON, anything else must be base64. Compressed data is
+        // recognised by its magic bytes, both as raw bytes (stdin, @file) and inside base64.
+        fn decode<'a>(bytes: Vec<u8>, forced: Option<bool>) -> Attempt<'a,Value> {
+
+            let text = match String::from_utf8(decompress(bytes)?) {
+
+                Ok(text) => text,
+                Err(err) => return fail(format!("JSON::read_payload - Payload is not UTF-8: {}", err)).category(Category::Config),
+
+            };
+
+            let trimmed = text.trim();
+
+            let base64 = forced.unwrap_or_else(|| !(trimmed.starts_with('{') || trimmed.starts_with('[')));
+
+            let json_str = match base64 {
+
+                false => text,
+
+                true => match String::from_utf8(decompress(decode_base64(trimmed)?)?) {
+
+                    Ok(text) => text,
+                    Err(err) => return fail(format!("JSON::read_payload - Base64 payload does not decode to UTF-8: {}", err)).category(Category::Config),
+
+                },
+
+            };
+
+            return match serde_json::from_str(&json_str) {
+
+                Ok(val) => Ok(val),
+                Err(err) if base64 => fail(format!("JSON::read_payload - Base64 payload does not decode to JSON: {}", err)).category(Category::Config),
+                Err(err) => fail(format!("JSON::read_payload - Payload is not JSON: {}", err)).category(Category::Config),
+
+            };
+
+        }
+
+        // Accepts the standard and URL-safe alphabets, with or without padding, and ignores line breaks.
+        fn decode_base64<'a>(text: &str) -> Attempt<'a,Vec<u8>> {
+
+            let text: String = text.split_whitespace().collect();
+
+            let (engine, name) = match text.contains(['-', '_']) {
+
+                true => (&URL_SAFE_ANY, "URL-safe"),
+                false => (&STANDARD_ANY, "standard"),
+
+            };
+
+            return match engine.decode(&text) {
+
+                Ok(bytes) => Ok(bytes),
+                Err(err) => fail(format!("JSON::read_payload - Payload is not base64 ({} alphabet) or a JSON object or array: {}", name, err)).category(Category::Config),
+
+            };
+
+        }
+
+        #[cfg(feature = "compression")]
+        fn decompress<'a>(bytes: Vec<u8>) -> Attempt<'a,Vec<u8>> {
+
+            let (name, reader): (&str, Box<dyn Read + '_>) = if bytes.starts_with(&GZIP_MAGIC) {
+
+                ("gzip", Box::new(flate2::read::GzDecoder::new(&bytes[..])))
+
+            } else if bytes.starts_with(&ZSTD_MAGIC) {
+
+                ("zstd", Box::new(zstd::stream::read::Decoder::new(&bytes[..])?))
+
+            } else {
+
+                return Ok(bytes);
+
+            };
+
+            let mut out = Vec::new();
+
+            if let Err(err) = reader.take(MAX_DECOMPRESSED + 1).read_to_end(&mut out) {
+
+                return fail(format!("JSON::read_payload - Payload looks {} compressed but failed to decompress: {}", name, err)).category(Category::Config);
+
+            }
+
+            if out.len() as u64 > MAX_DECOMPRESSED {
+
+                return fail(format!("JSON::read_payload - Decompressed {} payload exceeds {} bytes", name, MAX_DECOMPRESSED)).category(Category::Config);
+
+            }
+
+            return Ok(out);
+
+        }
+
+        #[cfg(not(feature = "compression"))]
+        fn decompress<'a>(bytes: Vec<u8>) -> Attempt<'a,Vec<u8>> {
+
+            if bytes.starts_with(&GZIP_MAGIC) || bytes.starts_with(&ZSTD_MAGIC) {
+
+                return fail("JSON::read_payload - Payload is compressed, build with the `compression` feature to read it").category(Category::Config);
+
+            }
+
+            return Ok(bytes);
+
+        }
+
+    }
+
+    mod preprocess {
+
+        use crate::tools::*;
+        use super::{Path, Segment, flag_values};
+        use serde_json::{Value, Map};
+        use std::path::{Path as FsPath, PathBuf};
+
+        const INCLUDE_KEY: &str = "$include";
+        const PROFILES_KEY: &str = "profiles";
+        const PROFILE_ENV: &str = "PROJECT_PROFILE";
+
+        /// Resolves a config document before it is layered:
+        /// 1. `"$include": "common.json"` (or a list of files) anywhere in an object pulls in that file,
+        ///    relative to the including file. Keys next to the directive override the included ones.
+        /// 2. The selected entry of the top-level `"profiles"` object is merged over the document,
+        ///    and `"profiles"` itself is dropped.
+        /// 3. `${VAR}` in strings is replaced by the environment variable, `${VAR:-fallback}` gives a
+        ///    fallback and `$${` writes a literal `${`.
+        ///
+        /// Returns the document and the names of the profiles it defines.
+        pub fn preprocess<'a>(doc: Value, origin: Option<&FsPath>, profile: Option<&str>) -> Attempt<'a,(Value, Vec<String>)> {
+
+            let base = origin.and_then(FsPath::parent).map(FsPath::to_path_buf).unwrap_or_default();
+            let mut stack: Vec<PathBuf> = origin.and_then(|file| file.canonicalize().ok()).into_iter().collect();
+
+            let mut doc = include(doc, &base, &mut stack)?;
+
+            let names = apply_profile(&mut doc, profile)?;
+
+            interpolate(&mut doc, Path::root())?;
+
+            return Ok((doc, names));
+
+        }
+
+        /// `--profile <name>` wins over `PROJECT_PROFILE`.
+        pub fn selected_profile<'a>() -> Attempt<'a,Option<String>> {
+
+            return Ok(flag_values("--profile")?.pop().or_else(|| std::env::var(PROFILE_ENV).ok()));
+
+        }
+
+        pub fn check_profile<'a>(profile: Option<&str>, defined: &[String]) -> Attempt<'a,()> {
+
+            match profile {
+
+                Some(name) if !defined.iter().any(|defined| defined == name) => fail(format!(
+                    "JSON::load - Profile {:?} is not defined in any config document. Available: {}",
+                    name, if defined.is_empty() {"none".to_string()} else {defined.join(", ")}
+                )).category(Category::Config),
+
+                _ => Ok(()),
+
+            }
+
+        }
+
+        fn include<'a>(val: Value, base: &FsPath, stack: &mut Vec<PathBuf>) -> Attempt<'a,Value> {
+
+            let mut map = match val {
+
+                Value::Object(map) => map,
+                Value::Array(ary) => return ary.into_iter().map(|val| include(val, base, stack)).collect::<Attempt<Vec<_>>>().map(Value::Array),
+                other => return Ok(other),
+
+            };
+
+            let targets = match map.remove(INCLUDE_KEY) {
+
+                None => Vec::new(),
+                Some(Value::String(file)) => vec![file],
+                Some(Value::Array(files)) if files.iter().all(Value::is_string) => files.into_iter().filter_map(|file| file.as_str().map(String::from)).collect(),
+                Some(other) => return fail(format!("JSON::load - {} must be a file name or a list of them, found {}", INCLUDE_KEY, other)).category(Category::Config),
+
+            };
+
+            let mut out = Value::Object(Map::new());
+
+            for target in targets {
+
+                let file = base.join(&target);
+
+                let canon = match file.canonicalize() {
+
+                    Ok(canon) => canon,
+                    Err(err) => return fail(format!("JSON::load - Failed to include {}: {}", file.display(), err)).category(Category::Io),
+
+                };
+
+                if stack.contains(&canon) {
+
+                    let chain: Vec<String> = stack.iter().chain([&canon]).map(|file| file.display().to_string()).collect();
+
+                    return fail(format!("JSON::load - Include cycle: {}", chain.join(" -> "))).category(Category::Config);
+
+                }
+
+                let text = std::fs::read_to_string(&canon)?;
+
+                let doc: Value = match serde_json::from_str(&text) {
+
+                    Ok(doc) => doc,
+                    Err(err) => return fail(format!("JSON::load - Included file {} is not valid JSON: {}", canon.display(), err)).category(Category::Config),
+
+                };
+
+                let dir = canon.parent().map(FsPath::to_path_buf).unwrap_or_default();
+
+                stack.push(canon);
+
+                let doc = include(doc, &dir, stack)?;
+
+                stack.pop();
+
+                overlay(&mut out, doc);
+
+            }
+
+            let mut rest = Map::new();
+
+            for (key, val) in map {rest.insert(key, include(val, base, stack)?);}
+
+            overlay(&mut out, Value::Object(rest));
+
+            return Ok(out);
+
+        }
+
+        fn apply_profile<'a>(doc: &mut Value, profile: Option<&str>) -> Attempt<'a,Vec<String>> {
+
+            let profiles = match doc.as_object_mut().and_then(|map| map.remove(PROFILES_KEY)) {
+
+                None => return Ok(Vec::new()),
+                Some(Value::Object(profiles)) => profiles,
+                Some(other) => return fail(format!("JSON::load - \"{}\" must be an object of named profiles, found {}", PROFILES_KEY, other)).category(Category::Config),
+
+            };
+
+            let names = profiles.keys().cloned().collect();
+
+            if let Some(selected) = profile.and_then(|name| profiles.get(name)) {overlay(doc, selected.clone());}
+
+            return Ok(names);
+
+        }
+
+        // Objects merge key by key, anything else replaces the value underneath.
+        fn overlay(dst: &mut Value, src: Value) {
+
+            match (dst, src) {
+
+                (Value::Object(dst), Value::Object(src)) => for (key, val) in src {
+
+                    match dst.get_mut(&key) {Some(existing) => overlay(existing, val), None => {dst.insert(key, val);}}
+
+                },
+
+                (dst, src) => *dst = src,
+
+            }
+
+        }
+
+        fn interpolate<'a>(val: &mut Value, at: Path) -> Attempt<'a,()> {
+
+            match val {
+
+                Value::String(text) => if text.contains('$') {*text = expand(text, &at)?;},
+
+                Value::Array(ary) => for (index, child) in ary.iter_mut().enumerate() {
+
+                    let mut path = at.clone(); path.push(Segment::Index(index)); interpolate(child, path)?;
+
+                },
+
+                Value::Object(map) => for (key, child) in map.iter_mut() {
+
+                    let mut path = at.clone(); path.push(Segment::Key(key.clone())); interpolate(child, path)?;
+
+                },
+
+                _ => {},
+
+            }
+
+            return Ok(());
+
+        }
+
+        fn expand<'a>(text: &str, at: &Path) -> Attempt<'a,String> {
+
+            let mut out = String::with_capacity(text.len());
+            let mut rest = text;
+
+            while let Some(start) = rest.find('$') {
+
+                out += &rest[..start];
+                rest = &rest[start..];
+
+                if let Some(after) = rest.strip_prefix("$${") {out += "${"; rest = after; continue;}
+
+                let body = match rest.strip_prefix("${") {
+
+                    Some(body) => body,
+                    None => {out += "$"; rest = &rest[1..]; continue;},
+
+                };
+
+                // Only the path, the value may be a secret that isn't registered yet
+                let end = body.find('}').attempt(format!("JSON::load - Unclosed ${{ in the value at '{}'", at)).category(Category::Config)?;
+
+                let (name, fallback) = match body[..end].split_once(":-") {
+
+                    Some((name, fallback)) => (name, Some(fallback)),
+                    None => (&body[..end], None),
+
+                };
+
+                out += &match (env_var(name), fallback) {
+
+                    (Some(val), _) => val,
+                    (None, Some(fallback)) => fallback.to_string(),
+                    (None, None) => return fail(format!("JSON::load - Environment variable {} is not set, used at '{}'", name, at)).category(Category::Config),
+
+                };
+
+                rest = &body[end + 1..];
+
+            }
+
+            out += rest;
+
+            return Ok(out);
+
+        }
+
+        // `HOME` is not set on Windows, where `USERPROFILE` plays the same role.
+        fn env_var(name: &str) -> Option<String> {
+
+            match (std::env::var(name).ok(), name) {
+
+                (Some(val), _) => Some(val),
+                (None, "HOME") => std::env::var("USERPROFILE").ok(),
+                (None, _) => None,
+
+            }
+
+        }
+
+        #[cfg(test)]
+        mod tests {
+
+            use super::*;
+            use serde_json::json;
+
+            // A fresh directory per test, tests run in parallel.
+            fn scratch(name: &str, files: &[(&str, &str)]) -> PathBuf {
+
+                let dir = std::env::temp_dir().join(format!("preprocess_{}_{}", name, std::process::id()));
+
+                let _ = std::fs::remove_dir_all(&dir);
+
+                for (file, text) in files {
+
+                    let file = dir.join(file);
+
+                    std::fs::create_dir_all(file.parent().unwrap()).unwrap();
+                    std::fs::write(file, text).unwrap();
+
+                }
+
+                return dir;
+
+            }
+
+            fn load(file: &FsPath, profile: Option<&str>) -> Attempt<'static,(Value, Vec<String>)> {
+
+                let doc = serde_json::from_str(&std::fs::read_to_string(file).unwrap()).unwrap();
+
+                return preprocess(doc, Some(file), profile);
+
+            }
+
+            #[test]
+            fn includes_are_relative_to_the_including_file() {
+
+                let dir = scratch("relative", &[
+                    ("main.json", r#"{"$include": "sub/common.json", "a": 2, "nested": {"$include": ["sub/base.json"], "d": 4}}"#),
+                    ("sub/common.json", r#"{"$include": "base.json", "a": 1, "b": 1}"#),
+                    ("sub/base.json", r#"{"c": 3}"#),
+                ]);
+
+                let (doc, _) = load(&dir.join("main.json"), None).unwrap();
+
+                assert_eq!(doc, json!({"a": 2, "b": 1, "c": 3, "nested": {"c": 3, "d": 4}}));
+
+            }
+
+            #[test]
+            fn include_cycles_fail() {
+
+                let dir = scratch("cycle", &[
+                    ("a.json", r#"{"$include": "b.json"}"#),
+                    ("b.json", r#"{"$include": "a.json"}"#),
+                ]);
+
+                let err = load(&dir.join("a.json"), None).unwrap_err();
+
+                assert!(err.msg.contains("Include cycle"), "{}", err.msg);
+                assert_eq!(err.category(), Category::Config);
+
+                let err = preprocess(json!({"$include": "missing.json"}), Some(&dir.join("a.json")), None).unwrap_err();
+
+                assert_eq!(err.category(), Category::Io);
+
+            }
+
+            #[test]
+            fn profiles_merge_over_the_document() {
+
+                let doc = json!({"port": 1, "db": {"host": "a", "user": "app"}, "profiles": {"dev": {"port": 2, "db": {"host": "b"}}, "prod": {}}});
+
+                let (out, names) = preprocess(doc.clone(), None, Some("dev")).unwrap();
+
+                assert_eq!(out, json!({"port": 2, "db": {"host": "b", "user": "app"}}));
+                assert_eq!(names, ["dev", "prod"]);
+
+                let (out, _) = preprocess(doc, None, None).unwrap();
+
+                assert_eq!(out, json!({"port": 1, "db": {"host": "a", "user": "app"}}));
+
+                let err = check_profile(Some("test"), &names).unwrap_err();
+
+                assert!(err.msg.contains("Available: dev, prod"), "{}", err.msg);
+                assert!(check_profile(Some("dev"), &names).is_ok());
+                assert!(preprocess(json!({"profiles": []}), None, None).is_err());
+
+            }
+
+            #[test]
+            fn expand_substitutes_variables() {
+
+                std::env::set_var("PREPROCESS_TEST_VAR", "value");
+
+                let at = Path::root();
+
+                assert_eq!(expand("a ${PREPROCESS_TEST_VAR} b", &at).unwrap(), "a value b");
+                assert_eq!(expand("${PREPROCESS_TEST_UNSET:-fallback}", &at).unwrap(), "fallback");
+                assert_eq!(expand("${PREPROCESS_TEST_VAR:-fallback}", &at).unwrap(), "value");
+                assert_eq!(expand("$${PREPROCESS_TEST_VAR}", &at).unwrap(), "${PREPROCESS_TEST_VAR}");
+                assert_eq!(expand("cost $5", &at).unwrap(), "cost $5");
+
+                let err = expand("${PREPROCESS_TEST_UNSET}", &at).unwrap_err();
+
+                assert!(err.msg.contains("PREPROCESS_TEST_UNSET is not set"), "{}", err.msg);
+
+                let err = expand("hunter22 ${PREPROCESS_TEST_VAR", &at).unwrap_err();
+
+                assert!(err.msg.contains("Unclosed") && !err.msg.contains("hunter22"), "{}", err.msg);
+
+            }
+
+        }
+
+    }
+
+    mod watch {
+
+        use crate::tools::*;
+        use super::{ToPath, preprocess, selected_profile};
+        use serde_json::Value;
+        use std::path::PathBuf;
+        use std::sync::{Arc, Mutex, RwLock, TryLockError, mpsc};
+        use std::time::{Duration, SystemTime};
+        use std::collections::VecDeque;
+
+        // Shared so `reload` can call them without holding `subscribers`, a callback may subscribe again.
+        type Callback = Arc<dyn Fn(&[Change]) + Send + Sync>;
+
+        pub struct WatchState {
+            file: PathBuf,
+            schema: Schema,
+            current: RwLock<Arc<JSON>>,
+            subscribers: Mutex<Vec<(Vec<String>, Callback)>>,
+            stamp: Mutex<Option<(SystemTime, u64)>>,
+            last_error: Mutex<Option<String>>,
+            reloading: Mutex<()>,
+            pending: Mutex<VecDeque<(Arc<JSON>, Arc<JSON>)>>,
+            notifying: Mutex<()>,
+        }
+
+        impl JSON {
+
+            /// Reads a config document from `file`, preprocesses it like `JSON::load` does for
+            /// `project.json`, applies the `--patch`/`--set` overrides and checks it against `schema`.
+            pub fn from_file<'a>(file: impl Into<PathBuf>, schema: &Schema) -> Attempt<'a,Self> {
+
+                let file = file.into();
+
+                let text = match std::fs::read_to_string(&file) {
+
+                    Ok(text) => text,
+                    Err(err) => return fail(format!("JSON::from_file - Failed to read {}: {}", file.display(), err)).category(Category::Io),
+
+                };
+
+                let doc: Value = match serde_json::from_str(&text) {
+
+                    Ok(doc) => doc,
+                    Err(err) => return fail(format!("JSON::from_file - {} is not valid JSON: {}", file.display(), err)).category(Category::Config),
+
+                };
+
+                let profile = selected_profile()?;
+
+                let (doc, profiles) = preprocess(doc, Some(&file), profile.as_deref())?;
+
+                return JSON::from_value(doc, Source::File(file)).finish(profile.as_deref(), &profiles, schema);
+
+            }
+
+        }
+
+        impl ConfigWatch {
+
+            /// Loads `file` and checks it for changes every `interval` on a background thread.
+            /// A changed file is reloaded and validated; if it is invalid the last good config stays
+            /// active and the reason is kept in `ConfigWatch::last_error`.
+            pub fn new<'a>(file: impl Into<PathBuf>, schema: Schema, interval: Duration) -> Attempt<'a,Self> {
+
+                let file = file.into();
+                let json = JSON::from_file(&file, &schema)?;
+
+                let state = Arc::new(WatchState {
+                    stamp: Mutex::new(stamp(&file)),
+                    file, schema,
+                    current: RwLock::new(Arc::new(json)),
+                    subscribers: Mutex::new(Vec::new()),
+                    last_error: Mutex::new(None),
+                    reloading: Mutex::new(()),
+                    pending: Mutex::new(VecDeque::new()),
+                    notifying: Mutex::new(()),
+                });
+
+                let (stop, stopped) = mpsc::channel::<()>();
+                let shared = state.clone();
+
+                // Dropping the sender ends the wait early, so the thread stops as soon as the watch is dropped.
+                let thread = std::thread::spawn(move || {
+
+                    while let Err(mpsc::RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+
+                        if shared.changed() {shared.reload();}
+
+                    }
+
+                });
+
+                return Ok(ConfigWatch {state, stop: Some(stop), thread: Some(thread)});
+
+            }
+
+            /// The config as of the last successful (re)load.
+            pub fn current(&self) -> Arc<JSON> {self.state.current()}
+
+            /// Calls `callback` after each reload in which any of `paths` changed, with the old and new value
+            /// of each changed path (`None` when it does not exist).
+            pub fn subscribe<'a>(&self, paths: &[&str], callback: impl Fn(&[Change]) + Send + Sync + 'static) -> Attempt<'a,()> {
+
+                let mut pointers = Vec::new();
+
+                for path in paths {pointers.push(path.to_path()?.to_string());}
+
+                lock(&self.state.subscribers).push((pointers, Arc::new(callback)));
+
+                return Ok(());
+
+            }
+
+            /// Reloads now instead of waiting for the next check. Returns whether the new config was accepted.
+            pub fn reload(&self) -> bool {self.state.reload()}
+
+            /// Why the most recent reload was rejected, cleared by the next successful one.
+            pub fn last_error(&self) -> Option<String> {lock(&self.state.last_error).clone()}
+
+        }
+
+        impl WatchState {
+
+            fn current(&self) -> Arc<JSON> {self.current.read().unwrap_or_else(|poison| poison.into_inner()).clone()}
+
+            fn changed(&self) -> bool {
+
+                let now = stamp(&self.file);
+                let mut last = lock(&self.stamp);
+
+                if now == *last {return false;}
+
+                *last = now; return true;
+
+            }
+
+            fn reload(&self) -> bool {
+
+                // `ConfigWatch::reload` and the watcher thread take turns, so neither swaps in an older file.
+                let reloading = lock(&self.reloading);
+
+                let new = match JSON::from_file(&self.file, &self.schema) {
+
+                    Ok(new) => Arc::new(new),
+
+                    Err(err) => {
+
+                        *lock(&self.last_error) = Some(format!("ConfigWatch - Rejected reload of {}: {}", self.file.display(), err.msg));
+
+                        return false;
+
+                    },
+
+                };
+
+                let old = std::mem::replace(&mut *self.current.write().unwrap_or_else(|poison| poison.into_inner()), new.clone());
+
+                *lock(&self.last_error) = None;
+
+                // Queued while `reloading` is held, so the queue is in the order the configs were swapped in.
+                lock(&self.pending).push_back((old, new));
+
+                drop(reloading);
+
+                self.notify();
+
+                return true;
+
+            }
+
+            // Delivers the queued changes in order. Only the thread holding `notifying` calls subscribers,
+            // a reload from another thread or from inside a callback just queues its change for it.
+            fn notify(&self) {
+
+                loop {
+
+                    let notifying = match self.notifying.try_lock() {
+
+                        Ok(guard) => guard,
+                        Err(TryLockError::Poisoned(poison)) => poison.into_inner(),
+                        Err(TryLockError::WouldBlock) => return,
+
+                    };
+
+                    loop {
+
+                        let next = lock(&self.pending).pop_front();
+
+                        let Some((old, new)) = next else {break;};
+
+                        let subscribers = lock(&self.subscribers).clone();
+
+                        for (paths, callback) in subscribers.iter() {
+
+                            let changes: Vec<Change> = paths.iter()
+                                .map(|path| Change {path: path.clone(), old: old.find(path).ok().cloned(), new: new.find(path).ok().cloned()})
+                                .filter(|change| change.old != change.new)
+                                .collect();
+
+                            if !changes.is_empty() {callback(&changes);}
+
+                        }
+
+                    }
+
+                    drop(notifying);
+
+                    // A change queued between the last pop and the unlock has nobody else to deliver it.
+                    if lock(&self.pending).is_empty() {return;}
+
+                }
+
+            }
+
+        }
+
+        impl Drop for ConfigWatch {
+
+            fn drop(&mut self) {
+
+                drop(self.stop.take());
+
+                if let Some(thread) = self.thread.take() {let _ = thread.join();}
+
+            }
+
+        }
+
+        // Modification time and length, so edits within the timestamp resolution are still noticed.
+        fn stamp(file: &PathBuf) -> Option<(SystemTime, u64)> {
+
+            let meta = std::fs::metadata(file).ok()?;
+
+            return Some((meta.modified().ok()?, meta.len()));
+
+        }
+
+        fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_,T> {mutex.lock().unwrap_or_else(|poison| poison.into_inner())}
+
+    }
+
+    mod layers {
+
+        use crate::tools::*;
+        use super::{Path, Segment, ToPath, read_payload, preprocess, selected_profile};
+        use serde_json::{Value, Map, json};
+        use std::path::PathBuf;
+
+        const CONFIG_FILE: &str = "project.json";
+        const ENV_PREFIX: &str = "PROJECT__";
+
+        impl JSON {
+
+            /// Builds the run configuration from every layer, later layers overriding earlier ones:
+            /// `defaults` < `project.json` < `PROJECT__*` environment variables < payload < `--patch`/`--set`.
+            /// Objects are merged key by key, any other value replaces what was there.
+            /// The config file and payload are preprocessed first (`$include`, the profile picked with
+            /// `--profile` or `PROJECT_PROFILE`, `${ENV_VAR}`). The merged result is checked against `schema`.
+            pub fn load<'a>(defaults: Value, schema: &Schema) -> Attempt<'a,Self> {
+
+                let mut json = JSON::from_value(defaults, Source::Default);
+                let mut profiles = Vec::new();
+                let profile = selected_profile()?;
+
+                if let Some(file) = read_file(CONFIG_FILE)? {
+
+                    let (file, found) = preprocess(file, Some(std::path::Path::new(CONFIG_FILE)), profile.as_deref())?;
+
+                    profiles.extend(found);
+                    json.merge_layer(file, Source::File(CONFIG_FILE.into()));
+
+                }
+
+                for (name, value) in env_layers() {json.merge_layer(value, Source::Env(name));}
+
+                if let Some((payload, source)) = read_payload()? {
+
+                    let (payload, found) = preprocess(payload, source.origin(), profile.as_deref())?;
+
+                    profiles.extend(found);
+                    json.merge_layer(payload, source);
+
+                }
+
+                return json.finish(profile.as_deref(), &profiles, schema);
+
+            }
+
+            /// The effective configuration with the source of every value, plus each `get_or`
+            /// call so far that fell back to its default.
+            pub fn provenance(&self) -> Value {
+
+                let mut found = Vec::new();
+
+                leaves(&self.root, Path::root(), &mut found);
+
+                let values: Map<String, Value> = found.into_iter().map(|(path, val)| {
+
+                    let source = self.source(&path).map(|source| source.to_string()).unwrap_or_else(|err| err.msg.to_string());
+
+                    let val = if self.is_secret(&path) {json!("***")} else {val.clone()};
+
+                    (path.to_string(), json!({"value": val, "source": source}))
+
+                }).collect();
+
+                let fallbacks: Vec<Value> = self.fallbacks.lock().unwrap_or_else(|poison| poison.into_inner()).iter()
+                    .map(|fb| json!({"path": fb.path, "default": fb.default, "reason": fb.reason}))
+                    .collect();
+
+                return json!({"values": values, "fallbacks": fallbacks});
+
+            }
+
+            pub fn merge_layer(&mut self, layer: Value, source: Source) {
+
+                merge(&mut self.root, layer, Path::root(), &source, &mut self.sources);
+
+            }
+
+            /// The layer the value at `path` came from, inherited from the closest recorded parent.
+            pub fn source<'a>(&'a self, path: impl ToPath) -> Attempt<'a,&'a Source> {
+
+                let path = path.to_path()?;
+
+                path.resolve(&self.root)?;
+
+                return (0..=path.segments().len()).rev()
+                    .find_map(|len| self.sources.get(&path.pointer(len)))
+                    .attempt(format!("JSON::source - No source recorded for '{}'", path));
+
+            }
+
+        }
+
+        fn leaves<'v>(val: &'v Value, at: Path, out: &mut Vec<(Path, &'v Value)>) {
+
+            match val {
+
+                Value::Object(map) if !map.is_empty() => for (key, child) in map {
+
+                    let mut path = at.clone(); path.push(Segment::Key(key.clone())); leaves(child, path, out);
+
+                },
+
+                Value::Array(ary) if !ary.is_empty() => for (index, child) in ary.iter().enumerate() {
+
+                    let mut path = at.clone(); path.push(Segment::Index(index)); leaves(child, path, out);
+
+                },
+
+                _ => out.push((at, val)),
+
+            }
+
+        }
+
+        fn merge(dst: &mut Value, src: Value, at: Path, source: &Source, sources: &mut std::collections::BTreeMap<String, Source>) {
+
+            let (dst, src) = match (dst, src) {
+
+                (Value::Object(dst), Value::Object(src)) => (dst, src),
+
+                (dst, src) => {
+
+                    let pointer = at.to_string();
+                    let children = format!("{}/", pointer);
+
+                    sources.retain(|key, _| key != &pointer && !key.starts_with(&children));
+                    sources.insert(pointer, source.clone());
+
+                    *dst = src; return;
+
+                }
+
+            };
+
+            for (key, val) in src {
+
+                let mut child = at.clone();
+
+                child.push(Segment::Key(key.clone()));
+
+                match dst.get_mut(&key) {
+
+                    Some(existing) => merge(existing, val, child, source, sources),
+
+                    None => {
+
+                        sources.insert(child.to_string(), source.clone());
+                        dst.insert(key, val);
+
+                    }
+
+                }
+
+            }
+
+        }
+
+        fn read_file<'a>(name: &str) -> Attempt<'a,Option<Value>> {
+
+            let text = match std::fs::read_to_string(name) {
+
+                Ok(text) => text,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+                Err(err) => return fail(format!("JSON::load - Failed to read {}: {}", PathBuf::from(name).display(), err)).category(Category::Io),
+
+            };
+
+            return match serde_json::from_str(&text) {
+
+                Ok(val) => Ok(Some(val)),
+                Err(err) => fail(format!("JSON::load - {} is not valid JSON: {}", PathBuf::from(name).display(), err)).category(Category::Config),
+
+            };
+
+        }
+
+        // `PROJECT__NETWORK_ARGS__PORT=6000` becomes `{"network_args": {"port": 6000}}`. Values that
+        // parse as JSON keep their type, anything else is taken as a string.
+        fn env_layers() -> Vec<(String, Value)> {
+
+            let mut vars: Vec<(String, String)> = std::env::vars()
+                .filter(|(name, _)| name.starts_with(ENV_PREFIX) && name.len() > ENV_PREFIX.len())
+                .collect();
+
+            vars.sort();
+
+            return vars.into_iter().map(|(name, raw)| {
+
+                let mut value = serde_json::from_str(&raw).unwrap_or(Value::String(raw));
+
+                for key in name[ENV_PREFIX.len()..].split("__").collect::<Vec<_>>().into_iter().rev() {
+
+                    let mut map = Map::new();
+
+                    map.insert(key.to_lowercase(), value);
+
+                    value = Value::Object(map);
+
+                }
+
+                (name, value)
+
+            }).collect();
+
+        }
+
+        impl Source {
+
+            /// The file a document was read from, used to resolve relative `$include` paths.
+            pub fn origin(&self) -> Option<&std::path::Path> {
+
+                match self {Source::File(path) => Some(path), _ => None}
+
+            }
+
+        }
+
+        impl std::fmt::Display for Source {
+
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+
+                match self {
+
+                    Source::Default => write!(f, "default"),
+                    Source::File(path) => write!(f, "file {}", path.display()),
+                    Source::Env(name) => write!(f, "env {}", name),
+                    Source::Argv => write!(f, "argv"),
+                    Source::Stdin => write!(f, "stdin"),
+                    Source::Cli(arg) => write!(f, "cli {}", arg),
+                    Source::Code => write!(f, "code"),
+
+                }
+
+            }
+
+        }
+
+    }
+
+    mod edit {
+
+        use crate::tools::*;
+        use super::{Path, Segment, ToPath, path::{array_index, kind}};
+        use serde_json::{Value, Map};
+        use base64::{Engine as _, engine::general_purpose::STANDARD};
+
+        // Where a value lands inside its parent container.
+        enum Slot<'v> {Key(&'v mut Map<String, Value>, String), Index(&'v mut Vec<Value>, usize)}
+
+        impl JSON {
+
+            /// Writes `value` at `path`, creating missing parent objects. Array elements can be replaced,
+            /// or appended with index `len` or `-`. Returns the value that was replaced, if any.
+            pub fn set<'a>(&mut self, path: impl ToPath, value: impl Into<Value>) -> Attempt<'a,Option<Value>> {
+
+                let mut path = path.to_path()?;
+
+                let old = match self.slot(&path, "JSON::set")? {
+
+                    None => Some(std::mem::replace(&mut self.root, value.into())),
+                    Some(Slot::Key(map, key)) => map.insert(key, value.into()),
+
+                    Some(Slot::Index(ary, index)) => {
+
+                        path = resolved(&path, index);
+
+                        if index == ary.len() {ary.push(value.into()); None} else {Some(std::mem::replace(&mut ary[index], value.into()))}
+
+                    },
+
+                };
+
+                self.mark(&path, Source::Code);
+                self.register_secrets();
+
+                return Ok(old);
+
+            }
+
+            /// Adds `value` at `path` without overwriting: object keys must not exist yet and array
+            /// elements are inserted before `index`, shifting the rest up.
+            pub fn insert<'a>(&mut self, path: impl ToPath, value: impl Into<Value>) -> Attempt<'a,()> {
+
+                let mut path = path.to_path()?;
+
+                match self.slot(&path, "JSON::insert")? {
+
+                    None => return fail("JSON::insert - Cannot insert at the document root, use JSON::set"),
+
+                    Some(Slot::Key(map, key)) => {
+
+                        if map.contains_key(&key) {return fail(format!("JSON::insert - '{}' already exists", path));}
+
+                        map.insert(key, value.into());
+
+                    },
+
+                    Some(Slot::Index(ary, index)) => {
+
+                        ary.insert(index, value.into());
+
+                        path = resolved(&path, index);
+
+                        if let Some((parent, _)) = path.split_last() {self.shift(&parent.to_string(), index, 1);}
+
+                    },
+
+                }
+
+                self.mark(&path, Source::Code);
+                self.register_secrets();
+
+                return Ok(());
+
+            }
+
+            /// Removes and returns the value at `path`. Later array elements shift down.
+            pub fn remove<'a>(&mut self, path: impl ToPath) -> Attempt<'a,Value> {
+
+                let path = path.to_path()?;
+
+                if let Err(err) = path.resolve(&self.root) {return fail(format!("JSON::remove - {}", err.msg));}
+
+                let (old, removed_from_array) = match self.slot(&path, "JSON::remove")? {
+
+                    None => return fail("JSON::remove - Cannot remove the document root"),
+                    Some(Slot::Key(map, key)) => (map.remove(&key).unwrap_or_default(), false),
+                    Some(Slot::Index(ary, index)) => (ary.remove(index), true),
+
+                };
+
+                self.forget(&path.to_string());
+
+                if let (Some((parent, seg)), true) = (path.split_last(), removed_from_array) {
+
+                    if let Some(index) = element(seg) {self.shift(&parent.to_string(), index + 1, -1);}
+
+                }
+
+                return Ok(old);
+
+            }
+
+            /// Applies an RFC 7396 merge patch at `path`: objects merge key by key, `null` deletes a key,
+            /// anything else replaces the current value.
+            pub fn merge<'a>(&mut self, path: impl ToPath, patch: impl Into<Value>) -> Attempt<'a,()> {
+
+                let path = path.to_path()?;
+
+                let mut target = match path.resolve(&self.root) {
+
+                    Ok(val) => val.clone(),
+                    Err(_) => Value::Null,
+
+                };
+
+                let mut touched = Vec::new();
+
+                merge_patch(&mut target, patch.into(), path.clone(), &mut touched);
+
+                // `set` marks the whole subtree, the parts the patch did not touch keep their sources.
+                let pointer = path.to_string();
+                let children = format!("{}/", pointer);
+
+                let kept: Vec<(String, Source)> = self.sources.iter()
+                    .filter(|(key, _)| **key == pointer || key.starts_with(&children))
+                    .map(|(key, source)| (key.clone(), source.clone()))
+                    .collect();
+
+                self.set(&path, target)?;
+
+                self.forget(&pointer);
+                self.sources.extend(kept);
+
+                for (path, removed) in touched {
+
+                    if removed {self.forget(&path.to_string());} else {self.mark(&path, Source::Code);}
+
+                }
+
+                return Ok(());
+
+            }
+
+            pub fn to_string_pretty(&self) -> String {serde_json::to_string_pretty(&self.root).unwrap_or_default()}
+
+            /// Standard base64 of the compact JSON, as accepted by `JSON::new`.
+            pub fn to_base64(&self) -> String {STANDARD.encode(self.root.to_string())}
+
+            /// Records `source` as the origin of the value at `path` and everything under it.
+            pub fn mark(&mut self, path: &Path, source: Source) {
+
+                let pointer = path.to_string();
+
+                self.forget(&pointer);
+                self.sources.insert(pointer, source);
+
+            }
+
+            fn forget(&mut self, pointer: &str) {
+
+                let children = format!("{}/", pointer);
+
+                self.sources.retain(|key, _| key != pointer && !key.starts_with(&children));
+
+            }
+
+            // Moves recorded sources of array elements at or after `from` by `delta` places.
+            fn shift(&mut self, array: &str, from: usize, delta: isize) {
+
+                let prefix = format!("{}/", array);
+
+                let renamed: Vec<(String, String)> = self.sources.keys()
+                    .filter_map(|key| {
+
+                        let rest = key.strip_prefix(&prefix)?;
+                        let (token, tail) = rest.split_once('/').map_or((rest, None), |(token, tail)| (token, Some(tail)));
+                        let index = array_index(token).filter(|index| *index >= from)?;
+                        let index = (index as isize + delta) as usize;
+
+                        Some((key.clone(), match tail {Some(tail) => format!("{}{}/{}", prefix, index, tail), None => format!("{}{}", prefix, index)}))
+
+                    })
+                    .collect();
+
+                let moved: Vec<(String, Source)> = renamed.into_iter()
+                    .filter_map(|(old, new)| self.sources.remove(&old).map(|source| (new, source)))
+                    .collect();
+
+                self.sources.extend(moved);
+
+            }
+
+            // Finds the container holding the last segment of `path`, creating missing parent objects.
+            // `None` means the path is the document root.
+            fn slot<'v>(&'v mut self, path: &Path, op: &str) -> Attempt<'static,Option<Slot<'v>>> {
+
+                let (parent, last) = match path.split_last() {
+
+                    Some(split) => split,
+                    None => return Ok(None),
+
+                };
+
+                let mut node = &mut self.root;
+
+                for (i, seg) in parent.segments().iter().enumerate() {
+
+                    if node.is_null() {*node = Value::Object(Map::new());}
+
+                    node = match (seg, node) {
+
+                        (Segment::Key(key), Value::Object(map)) => map.entry(key.clone()).or_insert(Value::Object(Map::new())),
+
+                        (seg, Value::Array(ary)) => {
+
+                            let len = ary.len();
+
+                            match element(seg).filter(|index| *index < len) {
+
+                                Some(index) => &mut ary[index],
+                                None => return fail(format!("{} - No element '{}' (length {}) at '{}'", op, seg, len, path.pointer(i))),
+
+                            }
+
+                        },
+
+                        (seg, other) => return fail(format!(
+                            "{} - Cannot create '{}' at '{}', value there is {}", op, seg, path.pointer(i), kind(other)
+                        )),
+
+                    };
+
+                }
+
+                if node.is_null() {*node = Value::Object(Map::new());}
+
+                return match (last, node) {
+
+                    (Segment::Key(key), Value::Object(map)) => Ok(Some(Slot::Key(map, key.clone()))),
+
+                    (seg, Value::Array(ary)) => {
+
+                        let len = ary.len();
+
+                        let index = match seg {Segment::Key(key) if key == "-" => Some(len), seg => element(seg)};
+
+                        match index {
+
+                            Some(index) if index <= len => Ok(Some(Slot::Index(ary, index))),
+                            _ => fail(format!("{} - No element '{}' (length {}) at '{}'", op, seg, len, parent)),
+
+                        }
+
+                    },
+
+                    (seg, other) => fail(format!("{} - Cannot set '{}' at '{}', value there is {}", op, seg, parent, kind(other))),
+
+                };
+
+            }
+
+        }
+
+        // `path` with its last segment replaced by the array index it resolved to, e.g. for `-`.
+        fn resolved(path: &Path, index: usize) -> Path {
+
+            let mut out = path.split_last().map(|(parent, _)| parent).unwrap_or_else(Path::root);
+
+            out.push(Segment::Index(index));
+
+            return out;
+
+        }
+
+        fn element(seg: &Segment) -> Option<usize> {
+
+            match seg {Segment::Index(index) => Some(*index), Segment::Key(key) => array_index(key)}
+
+        }
+
+        // `touched` collects every path the patch wrote (false) or deleted (true).
+        fn merge_patch(target: &mut Value, patch: Value, at: Path, touched: &mut Vec<(Path, bool)>) {
+
+            let patch = match patch {
+
+                Value::Object(patch) => patch,
+                other => {*target = other; touched.push((at, false)); return;},
+
+            };
+
+            if !target.is_object() {*target = Value::Object(Map::new());}
+
+            let map = match target {Value::Object(map) => map, _ => unreachable!("merge_patch - target was just made an object")};
+
+            for (key, val) in patch {
+
+                let mut path = at.clone(); path.push(Segment::Key(key.clone()));
+
+                if val.is_null() {map.remove(&key); touched.push((path, true)); continue;}
+
+                merge_patch(map.entry(key).or_insert(Value::Null), val, path, touched);
+
+            }
+
+        }
+
+        #[cfg(test)]
+        mod tests {
+
+            use super::*;
+            use super::super::tests::doc;
+            use serde_json::json;
+
+            #[test]
+            fn set_creates_parents_and_appends() {
+
+                let mut json = doc(json!({"peers": [1]}));
+
+                assert_eq!(json.set("net.port", 6000).unwrap(), None);
+                assert_eq!(json.set("peers[1]", 2).unwrap(), None);
+                assert_eq!(json.set("/peers/0", 0).unwrap(), Some(json!(1)));
+
+                assert_eq!(json.root, json!({"peers": [0, 2], "net": {"port": 6000}}));
+                assert!(matches!(json.source("net.port").unwrap(), Source::Code));
+
+                assert!(json.set("peers[5]", 5).is_err());
+
+            }
+
+            #[test]
+            fn insert_and_remove_shift_elements() {
+
+                let mut json = doc(json!({"a": {"b": 1}, "list": ["x", "z"]}));
+
+                json.insert("list[1]", "y").unwrap();
+
+                assert_eq!(json.root["list"], json!(["x", "y", "z"]));
+                assert!(json.insert("a.b", 2).is_err());
+
+                assert_eq!(json.remove("list[0]").unwrap(), "x");
+                assert_eq!(json.remove("a.b").unwrap(), 1);
+
+                assert_eq!(json.root, json!({"a": {}, "list": ["y", "z"]}));
+                assert!(json.remove("a.b").is_err());
+
+            }
+
+            #[test]
+            fn merge_follows_rfc_7396() {
+
+                let mut json = doc(json!({"db": {"host": "localhost", "port": 5432, "opts": {"ssl": true}}}));
+
+                json.merge("db", json!({"port": 6543, "opts": {"ssl": null, "timeout": 5}, "user": "app"})).unwrap();
+
+                assert_eq!(json.root, json!({"db": {"host": "localhost", "port": 6543, "opts": {"timeout": 5}, "user": "app"}}));
+
+                // Untouched values keep the file as their source, merged ones come from code
+                assert!(matches!(json.source("db.host").unwrap(), Source::File(_)));
+                assert!(matches!(json.source("db.port").unwrap(), Source::Code));
+
+                json.merge("db.opts", json!([1, 2])).unwrap();
+
+                assert_eq!(json.root["db"]["opts"], json!([1, 2]));
+
+                json.merge("cache", json!({"size": 10})).unwrap();
+
+                assert_eq!(json.root["cache"], json!({"size": 10}));
+
+            }
+
+        }
+
+    }
+
+    mod overrides {
+
+        use crate::tools::*;
+        use super::{Path, Segment, ToPath, flag_values, path::array_index};
+        use serde_json::Value;
+
+        impl JSON {
+
+            /// Applies the command line overrides on top of the loaded config: every `--patch <file>`
+            /// (RFC 6902 JSON Patch) in order, then every `--set <path>=<value>`. Each applied override
+            /// is tagged as a `Source::Cli` value and written to the run log.
+            pub fn apply_overrides<'a>(&mut self) -> Attempt<'a,()> {
+
+                for file in flag_values("--patch")? {
+
+                    let patch = match std::fs::read_to_string(&file) {
+
+                        Ok(text) => text,
+                        Err(err) => return fail(format!("JSON::apply_overrides - Failed to read patch file {}: {}", file, err)).category(Category::Io),
+
+                    };
+
+                    let patch: Value = match serde_json::from_str(&patch) {
+
+                        Ok(patch) => patch,
+                        Err(err) => return fail(format!("JSON::apply_overrides - Patch file {} is not JSON: {}", file, err)).category(Category::Config),
+
+                    };
+
+                    self.apply_patch(&patch, &file)?;
+
+                }
+
+                for arg in flag_values("--set")? {self.apply_set(&arg)?;}
+
+                return Ok(());
+
+            }
+
+            /// Applies one `<path>=<value>` override. The value keeps its JSON type when it parses as
+            /// JSON (`6000`, `true`, `[1,2]`, `"quoted"`), anything else is taken as a string.
+            pub fn apply_set<'a>(&mut self, arg: &str) -> Attempt<'a,()> {
+
+                let (name, raw) = arg.split_once('=')
+                    .attempt(format!("JSON::apply_set - Expected <path>=<value>, got {:?}", arg)).category(Category::Config)?;
+
+                let value = serde_json::from_str(raw).unwrap_or_else(|_| Value::String(raw.to_string()));
+
+                let path = name.to_path().category(Category::Config)?;
+
+                let old = self.set(&path, value.clone()).category(Category::Config)?;
+
+                // The path only, the value may be a secret and sources show up in `JSON::provenance`
+                self.mark(&path, Source::Cli(format!("--set {}", name)));
+
+                log(format!("JSON::apply_set - '{}' = {} (was {})", path, self.shown(&path, Some(&value)), self.shown(&path, old.as_ref())));
+
+                return Ok(());
+
+            }
+
+            /// Applies an RFC 6902 JSON Patch. Either every operation applies or the document is left unchanged.
+            pub fn apply_patch<'a>(&mut self, patch: &Value, origin: &str) -> Attempt<'a,()> {
+
+                let ops = patch.as_array()
+                    .attempt(format!("JSON::apply_patch - Patch {} must be an array of operations", origin)).category(Category::Config)?;
+
+                let (root, sources) = (self.root.clone(), self.sources.clone());
+                let mut applied = Vec::new();
+
+                for (i, op) in ops.iter().enumerate() {
+
+                    match self.apply_op(op, origin) {
+
+                        Ok(line) => applied.push(line),
+
+                        Err(err) => {
+
+                            self.root = root;
+                            self.sources = sources;
+
+                            return fail(format!("JSON::apply_patch - Operation {} of {} failed, patch not applied: {}", i, origin, err.msg)).category(Category::Config);
+
+                        },
+
+                    }
+
+                }
+
+                // Logged once the whole patch has applied, a rolled back patch leaves no trace in the run log
+                for line in applied {log(format!("JSON::apply_patch - {}: {}", origin, line));}
+
+                log(format!("JSON::apply_patch - Applied {} operation(s) from {}", ops.len(), origin));
+
+                return Ok(());
+
+            }
+
+            // Returns the run log line for the operation: its name, path and the value written there.
+            fn apply_op<'a>(&mut self, op: &Value, origin: &str) -> Attempt<'a,String> {
+
+                let name = op.get("op").and_then(Value::as_str).attempt("missing \"op\"")?;
+                let path = pointer(op, "path")?;
+                let source = Source::Cli(format!("--patch {}", origin));
+
+                let written = match name {
+
+                    "add" => Some(self.add(&path, member(op, "value")?)?),
+
+                    "remove" => {self.remove(&path)?; None},
+
+                    "replace" => {
+
+                        if let Err(err) = path.resolve(&self.root) {return fail(format!("replace target missing: {}", err.msg));}
+
+                        self.set(&path, member(op, "value")?)?;
+
+                        Some(path.clone())
+
+                    },
+
+                    "move" => {
+
+                        let from = pointer(op, "from")?;
+
+                        if path.to_string().starts_with(&format!("{}/", from)) {return fail(format!("cannot move '{}' into itself", from));}
+
+                        let value = self.remove(&from)?;
+
+                        Some(self.add(&path, value)?)
+
+                    },
+
+                    "copy" => {
+
+                        let value = match pointer(op, "from")?.resolve(&self.root) {
+
+                            Ok(value) => value.clone(),
+                            Err(err) => return fail(format!("copy source missing: {}", err.msg)),
+
+                        };
+
+                        Some(self.add(&path, value)?)
+
+                    },
+
+                    "test" => {
+
+                        let expected = member(op, "value")?;
+
+                        match path.resolve(&self.root) {
+
+                            Ok(found) if *found == expected => None,
+                            Ok(_) if self.is_secret(&path) => return fail(format!("test failed at '{}': value differs", path)),
+                            Ok(found) => return fail(format!("test failed at '{}': expected {}, found {}", path, expected, found)),
+                            Err(err) => return fail(format!("test target missing: {}", err.msg)),
+
+                        }
+
+                    },
+
+                    other => return fail(format!("unknown operation {:?}", other)),
+
+                };
+
+                let line = match written {
+
+                    Some(written) => {
+
+                        self.mark(&written, source);
+
+                        format!("{} '{}' = {}", name, written, self.shown(&written, written.resolve(&self.root).ok()))
+
+                    },
+
+                    None => format!("{} '{}'", name, path),
+
+                };
+
+                return Ok(line);
+
+            }
+
+            // RFC 6902 "add": the parent must exist, array elements are inserted (`-` appends),
+            // object members are created or replaced. Returns where the value landed.
+            fn add<'a>(&mut self, path: &Path, value: Value) -> Attempt<'a,Path> {
+
+                let (parent, last) = match path.split_last() {
+
+                    Some(split) => split,
+                    None => {self.set(path, value)?; return Ok(path.clone());},
+
+                };
+
+                let array_len = match parent.resolve(&self.root) {
+
+                    Ok(Value::Array(ary)) => Some(ary.len()),
+                    Ok(_) => None,
+                    Err(err) => return fail(format!("add target parent missing: {}", err.msg)),
+
+                };
+
+                let index = match (array_len, last) {
+
+                    (None, _) => {self.set(path, value)?; return Ok(path.clone());},
+                    (Some(len), Segment::Key(key)) if key == "-" => len,
+                    (Some(_), Segment::Key(key)) => array_index(key).attempt(format!("{:?} is not an array index", key))?,
+                    (Some(_), Segment::Index(index)) => *index,
+
+                };
+
+                let mut at = parent.clone();
+
+                at.push(Segment::Index(index));
+
+                self.insert(&at, value)?;
+
+                return Ok(at);
+
+            }
+
+        }
+
+        impl JSON {
+
+            // A value as the run log shows it, masked at secret paths whatever its length.
+            fn shown(&self, path: &Path, val: Option<&Value>) -> String {
+
+                match val {
+
+                    None => "unset".to_string(),
+                    Some(_) if self.is_secret(path) => "***".to_string(),
+                    Some(val) => val.to_string(),
+
+                }
+
+            }
+
+        }
+
+        fn pointer<'a>(op: &Value, key: &str) -> Attempt<'a,Path> {
+
+            let text = op.get(key).and_then(Value::as_str).attempt(format!("missing {:?}", key))?;
+
+            return Path::from_pointer(text);
+
+        }
+
+        fn member<'a>(op: &Value, key: &str) -> Attempt<'a,Value> {
+
+            return op.get(key).cloned().attempt(format!("missing {:?}", key));
+
+        }
+
+        // Overrides go to the run log when one is installed, they are always visible in `JSON::provenance`.
+        fn log(line: String) {
+
+            crate::tools::with_logger(|mng| mng.push_stdout(line));
+
+        }
+
+        #[cfg(test)]
+        mod tests {
+
+            use super::*;
+            use super::super::tests::doc;
+            use serde_json::json;
+
+            #[test]
+            fn patch_applies_every_operation() {
+
+                let mut json = doc(json!({"a": 1, "list": [1, 3], "old": {"k": "v"}}));
+
+                let patch = json!([
+                    {"op": "add", "path": "/b", "value": 2},
+                    {"op": "add", "path": "/list/1", "value": 2},
+                    {"op": "add", "path": "/list/-", "value": 4},
+                    {"op": "replace", "path": "/a", "value": 10},
+                    {"op": "move", "from": "/old", "path": "/new"},
+                    {"op": "copy", "from": "/new/k", "path": "/copied"},
+                    {"op": "remove", "path": "/b"},
+                    {"op": "test", "path": "/list", "value": [1, 2, 3, 4]},
+                ]);
+
+                json.apply_patch(&patch, "test.json").unwrap();
+
+                assert_eq!(json.root, json!({"a": 10, "list": [1, 2, 3, 4], "new": {"k": "v"}, "copied": "v"}));
+                assert!(matches!(json.source("/a").unwrap(), Source::Cli(_)));
+
+            }
+
+            #[test]
+            fn patch_is_all_or_nothing() {
+
+                let before = json!({"a": 1, "list": [1]});
+                let mut json = doc(before.clone());
+
+                let patch = json!([
+                    {"op": "add", "path": "/b", "value": 2},
+                    {"op": "remove", "path": "/list/0"},
+                    {"op": "replace", "path": "/missing", "value": 3},
+                ]);
+
+                let err = json.apply_patch(&patch, "test.json").unwrap_err();
+
+                assert!(err.msg.contains("Operation 2 of test.json failed"), "{}", err.msg);
+                assert_eq!(json.root, before);
+                assert!(matches!(json.source("/a").unwrap(), Source::File(_)));
+
+            }
+
+            #[test]
+            fn patch_rejects_bad_operations() {
+
+                let mut json = doc(json!({"a": {"b": 1}, "list": []}));
+
+                for op in [
+                    json!({"path": "/a"}),
+                    json!({"op": "frobnicate", "path": "/a"}),
+                    json!({"op": "add", "path": "/x/y", "value": 1}),
+                    json!({"op": "add", "path": "/list/01", "value": 1}),
+                    json!({"op": "move", "from": "/a", "path": "/a/c"}),
+                    json!({"op": "copy", "from": "/nope", "path": "/c"}),
+                    json!({"op": "test", "path": "/a/b", "value": 2}),
+                ] {
+
+                    assert!(json.apply_patch(&json!([op]), "test.json").is_err(), "{} should fail", op);
+
+                }
+
+                assert!(json.apply_patch(&json!({"op": "add"}), "test.json").is_err());
+
+            }
+
+            #[test]
+            fn failed_test_hides_secrets() {
+
+                let mut json = doc(json!({"db_password": "hunter2", "port": 1}));
+
+                json.register_secrets();
+
+                let err = json.apply_patch(&json!([{"op": "test", "path": "/db_password", "value": "guess"}]), "test.json").unwrap_err();
+
+                assert!(!err.msg.contains("hunter2"), "{}", err.msg);
+
+                let err = json.apply_patch(&json!([{"op": "test", "path": "/port", "value": 2}]), "test.json").unwrap_err();
+
+                assert!(err.msg.contains("expected 2, found 1"), "{}", err.msg);
+
+            }
+
+            #[test]
+            fn set_keeps_json_types() {
+
+                let mut json = doc(json!({}));
+
+                json.apply_set("net.port=6000").unwrap();
+                json.apply_set("net.peers=[1,2]").unwrap();
+                json.apply_set("name=hello world").unwrap();
+                json.apply_set("quoted=\"6000\"").unwrap();
+
+                assert_eq!(json.root, json!({"net": {"port": 6000, "peers": [1, 2]}, "name": "hello world", "quoted": "6000"}));
+                assert!(json.apply_set("no_equals").is_err());
+
+            }
+
+            #[test]
+            fn overrides_keep_secrets_out_of_sources_and_logs() {
+
+                let mut json = doc(json!({"port": 1}));
+
+                json.apply_set("db_password=hunter22").unwrap();
+
+                assert!(matches!(json.source("db_password").unwrap(), Source::Cli(label) if label == "--set db_password"));
+
+                let line = json.apply_op(&json!({"op": "add", "path": "/api_token", "value": "abc"}), "test.json").unwrap();
+
+                assert_eq!(line, "add '/api_token' = ***");
+
+                let line = json.apply_op(&json!({"op": "replace", "path": "/port", "value": 2}), "test.json").unwrap();
+
+                assert_eq!(line, "replace '/port' = 2");
+
+            }
+
+        }
+
+    }
+
+    mod secrets {
+
+        use crate::tools::*;
+        use super::{Path, Segment, ToPath};
+        use serde_json::Value;
+
+        // Keys containing any of these (case-insensitive) are secret, e.g. `db_password` or `access_token`.
+        const SENSITIVE_KEYS: [&str; 7] = ["password", "passwd", "secret", "token", "api_key", "apikey", "private_key"];
+
+        // Top-level config key listing extra secret paths, e.g. `"redact": ["network_args.peers[0].ip"]`.
+        pub const REDACT_KEY: &str = "redact";
+
+        impl JSON {
+
+            /// True if `path` or one of its parents has a sensitive key name or is listed under `"redact"`.
+            pub fn is_secret(&self, path: &Path) -> bool {
+
+                let by_name = path.segments().iter().any(|seg| match seg {
+
+                    Segment::Key(key) => SENSITIVE_KEYS.iter().any(|word| key.to_lowercase().contains(word)),
+                    Segment::Index(_) => false,
+
+                });
+
+                if by_name {return true;}
+
+                let pointer = path.to_string();
+
+                return self.redacted_paths().iter().any(|listed| pointer == *listed || pointer.starts_with(&format!("{}/", listed)));
+
+            }
+
+            /// Registers every secret string in the document with `add_secret`, so it is masked in
+            /// `Fail` messages and log output from then on. Secret numbers are not registered, their
+            /// digits would also mask line numbers and ports; `get` and `validate` leave them out by path.
+            pub fn register_secrets(&self) {
+
+                let mut stack = vec![(Path::root(), &self.root)];
+
+                while let Some((path, val)) = stack.pop() {
+
+                    match val {
+
+                        Value::Object(map) => for (key, child) in map {
+
+                            let mut path = path.clone(); path.push(Segment::Key(key.clone())); stack.push((path, child));
+
+                        },
+
+                        Value::Array(ary) => for (index, child) in ary.iter().enumerate() {
+
+                            let mut path = path.clone(); path.push(Segment::Index(index)); stack.push((path, child));
+
+                        },
+
+                        Value::String(text) if self.is_secret(&path) => add_secret(text.as_str()),
+
+                        _ => {},
+
+                    }
+
+                }
+
+            }
+
+            fn redacted_paths(&self) -> Vec<String> {
+
+                let listed = match self.root.get(REDACT_KEY).and_then(Value::as_array) {
+
+                    Some(listed) => listed,
+                    None => return Vec::new(),
+
+                };
+
+                return listed.iter()
+                    .filter_map(Value::as_str)
+                    .filter_map(|path| path.to_path().ok())
+                    .map(|path| path.to_string())
+                    .collect();
+
+            }
+
+        }
+
+    }
+
+    mod schema {
+
+        use crate::tools::*;
+        use super::{Convert, Path, Segment, as_integer, path::kind};
+        use serde_json::Value;
+
+        impl Schema {
+
+            pub fn integer(min: i128, max: i128) -> Self {Schema::Integer {min: Some(min), max: Some(max)}}
+
+            pub fn number(min: f64, max: f64) -> Self {Schema::Number {min: Some(min), max: Some(max)}}
+
+            pub fn one_of<V: Into<Value>>(values: impl IntoIterator<Item = V>) -> Self {Schema::Enum(values.into_iter().map(Into::into).collect())}
+
+            pub fn array(item: Schema) -> Self {Schema::Array(Box::new(item))}
+
+            pub fn object(fields: impl IntoIterator<Item = Field>) -> Self {Schema::Object(fields.into_iter().collect())}
+
+            /// Accepts any value `JSON::get::<T>` would accept, e.g. `Schema::of::<Duration>()`.
+            pub fn of<T>() -> Self where for<'a> Value: Convert<'a,T> {Schema::Check(std::any::type_name::<T>(), check::<T>)}
+
+            // `doc` tells which paths are secret, their values are left out of the messages.
+            fn validate(&self, val: &Value, at: &Path, doc: &JSON, out: &mut Vec<String>) {
+
+                let secret = doc.is_secret(at);
+                let shown = if secret {"***".to_string()} else {val.to_string()};
+
+                let mut report = |msg: String| out.push(format!("'{}': {}", at, msg));
+
+                match (self, val) {
+
+                    (Schema::Any, _) | (Schema::Bool, Value::Bool(_)) | (Schema::Str, Value::String(_)) => {},
+
+                    (Schema::Integer {min, max}, Value::Number(_)) => match as_integer(val) {
+
+                        Some(x) if min.map_or(false, |min| x < min) => report(format!("{} is below the minimum {}", shown, min.unwrap_or_default())),
+                        Some(x) if max.map_or(false, |max| x > max) => report(format!("{} is above the maximum {}", shown, max.unwrap_or_default())),
+                        Some(_) => {},
+                        None => report(format!("expected an integer, found {}", shown)),
+
+                    },
+
+                    (Schema::Number {min, max}, Value::Number(num)) => {
+
+                        let x = num.as_f64().unwrap_or(f64::NAN);
+
+                        if min.map_or(false, |min| x < min) {report(format!("{} is below the minimum {}", shown, min.unwrap_or_default()));}
+                        if max.map_or(false, |max| x > max) {report(format!("{} is above the maximum {}", shown, max.unwrap_or_default()));}
+
+                    },
+
+                    (Schema::Enum(allowed), _) => if !allowed.contains(val) {
+
+                        let allowed: Vec<String> = allowed.iter().map(|val| val.to_string()).collect();
+
+                        report(format!("{} is not one of {}", shown, allowed.join(", ")));
+
+                    },
+
+                    (Schema::Array(item), Value::Array(ary)) => for (index, child) in ary.iter().enumerate() {
+
+                        let mut path = at.clone(); path.push(Segment::Index(index)); item.validate(child, &path, doc, out);
+
+                    },
+
+                    (Schema::Object(fields), Value::Object(map)) => {
+
+                        for field in fields {
+
+                            let mut path = at.clone(); path.push(Segment::Key(field.name.clone()));
+
+                            match map.get(&field.name) {
+
+                                Some(child) => field.schema.validate(child, &path, doc, out),
+                                None if field.required => out.push(format!("'{}': required key is missing", path)),
+                                None => {},
+
+                            }
+
+                        }
+
+                        for key in map.keys().filter(|key| !fields.iter().any(|field| &field.name == *key)) {
+
+                            let closest = fields.iter()
+                                .map(|field| (distance(key, &field.name), &field.name))
+                                .filter(|(dist, name)| *dist <= 2.max(name.len() / 3))
+                                .min();
+
+                            let mut path = at.clone(); path.push(Segment::Key(key.clone()));
+
+                            match closest {
+
+                                Some((_, name)) => out.push(format!("'{}': unknown key, did you mean {:?}?", path, name)),
+                                None => out.push(format!("'{}': unknown key", path)),
+
+                            }
+
+                        }
+
+                    },
+
+                    (Schema::Check(name, check), _) => match check(val) {
+
+                        Some(_) if secret => report(format!("not a valid {}", name)),
+                        Some(msg) => report(msg),
+                        None => {},
+
+                    },
+
+                    (schema, _) => report(format!("expected {}, found {}", schema.expected(), kind(val))),
+
+                }
+
+            }
+
+            fn expected(&self) -> &'static str {
+
+                match self {
+
+                    Schema::Any => "anything",
+                    Schema::Bool => "a boolean",
+                    Schema::Str => "a string",
+                    Schema::Integer {..} => "an integer",
+                    Schema::Number {..} => "a number",
+                    Schema::Enum(_) => "one of the listed values",
+                    Schema::Array(_) => "an array",
+                    Schema::Object(_) => "an object",
+                    Schema::Check(name, _) => name,
+
+                }
+
+            }
+
+        }
+
+        impl Field {
+
+            pub fn required(name: impl Into<String>, schema: Schema) -> Self {Field {name: name.into(), required: true, schema}}
+
+            pub fn optional(name: impl Into<String>, schema: Schema) -> Self {Field {name: name.into(), required: false, schema}}
+
+        }
+
+        impl JSON {
+
+            /// Checks the whole document against `schema` and reports every violation at once.
+            pub fn validate<'a>(&self, schema: &Schema) -> Attempt<'a,()> {
+
+                let mut problems = Vec::new();
+
+                schema.validate(&self.root, &Path::root(), self, &mut problems);
+
+                if problems.is_empty() {return Ok(());}
+
+                return fail(format!("JSON::validate - {} problem(s) in the configuration:\n\t\t{}", problems.len(), problems.join("\n\t\t")))
+                    .category(Category::Config)
+                    .code("config.invalid");
+
+            }
+
+        }
+
+        fn check<T>(val: &Value) -> Option<String> where for<'a> Value: Convert<'a,T> {
+
+            return without_backtrace(|| val.make().err().map(|err| err.msg.to_string()));
+
+        }
+
+        // Levenshtein distance, used for "did you mean" suggestions on unknown keys.
+        fn distance(a: &str, b: &str) -> usize {
+
+            let b: Vec<char> = b.chars().collect();
+            let mut row: Vec<usize> = (0..=b.len()).collect();
+
+            for (i, ca) in a.chars().enumerate() {
+
+                let mut prev = row[0];
+
+                row[0] = i + 1;
+
+                for (j, cb) in b.iter().enumerate() {
+
+                    let cost = if ca == *cb {prev} else {prev + 1};
+
+                    prev = row[j + 1];
+                    row[j + 1] = cost.min(row[j] + 1).min(prev + 1);
+
+                }
+
+            }
+
+            return row[b.len()];
+
+        }
+
+    }
+
+    mod units {
+
+        use crate::tools::*;
+        use super::{Convert, as_integer};
+        use serde_json::Value;
+        use std::time::Duration;
+        use std::net::{IpAddr, SocketAddr};
+        use std::path::PathBuf;
+
+        const DURATION_UNITS: [(&str, u128); 8] = [
+            ("ns", 1), ("us", 1_000), ("µs", 1_000), ("ms", 1_000_000), ("s", 1_000_000_000),
+            ("m", 60_000_000_000), ("h", 3_600_000_000_000), ("d", 86_400_000_000_000),
+        ];
+
+        const SIZE_UNITS: [(&str, u64); 9] = [
+            ("b", 1), ("kb", 1_000), ("mb", 1_000_000), ("gb", 1_000_000_000), ("tb", 1_000_000_000_000),
+            ("kib", 1 << 10), ("mib", 1 << 20), ("gib", 1 << 30), ("tib", 1 << 40),
+        ];
+
+        /// Parses durations such as `"500ms"`, `"2s"` or `"1m30s"`. A bare number is taken as milliseconds.
+        pub fn parse_duration<'a>(text: &str) -> Attempt<'a,Duration> {
+
+            let text = text.trim();
+
+            if let Ok(ms) = text.parse::<u64>() {return Ok(Duration::from_millis(ms));}
+
+            if text.is_empty() {return fail("json_io::parse_duration - Empty duration");}
+
+            let mut nanos: u128 = 0;
+            let mut rest = text;
+
+            while !rest.is_empty() {
+
+                let (num, unit, next) = split_quantity(rest);
+
+                let num = num.parse::<f64>().ok().filter(|x| x.is_finite())
+                    .attempt(format!("json_io::parse_duration - Expected a number before {:?} in {:?}", unit, text))?;
+
+                let scale = DURATION_UNITS.iter().find(|(name, _)| *name == unit).map(|(_, scale)| *scale)
+                    .attempt(format!("json_io::parse_duration - Unknown unit {:?} in {:?} (expected one of ns, us, ms, s, m, h, d)", unit, text))?;
+
+                nanos = nanos.checked_add((num * scale as f64).round() as u128)
+                    .attempt(format!("json_io::parse_duration - Duration {:?} is too long", text))?;
+
+                rest = next;
+
+            }
+
+            let secs = u64::try_from(nanos / 1_000_000_000).ok()
+                .attempt(format!("json_io::parse_duration - Duration {:?} is too long", text))?;
+
+            return Ok(Duration::new(secs, (nanos % 1_000_000_000) as u32));
+
+        }
+
+        /// Parses sizes such as `"512"`, `"64KB"` or `"10MiB"`. A bare number is taken as bytes.
+        pub fn parse_size<'a>(text: &str) -> Attempt<'a,ByteSize> {
+
+            let text = text.trim();
+
+            if let Ok(bytes) = text.parse::<u64>() {return Ok(ByteSize(bytes));}
+
+            let (num, unit, rest) = split_quantity(text);
+
+            if !rest.is_empty() {return fail(format!("json_io::parse_size - Unexpected {:?} after {}{} in {:?}", rest, num, unit, text));}
+
+            let num = num.parse::<f64>().ok().filter(|x| x.is_finite() && *x >= 0.0)
+                .attempt(format!("json_io::parse_size - Expected a number before {:?} in {:?}", unit, text))?;
+
+            let scale = SIZE_UNITS.iter().find(|(name, _)| name.eq_ignore_ascii_case(unit)).map(|(_, scale)| *scale)
+                .attempt(format!("json_io::parse_size - Unknown unit {:?} in {:?} (expected B, KB, MB, GB, TB, KiB, MiB, GiB or TiB)", unit, text))?;
+
+            let bytes = num * scale as f64;
+
+            if bytes.fract() != 0.0 {return fail(format!("json_io::parse_size - {:?} is not a whole number of bytes", text));}
+
+            if bytes > u64::MAX as f64 {return fail(format!("json_io::parse_size - Size {:?} is too large", text));}
+
+            return Ok(ByteSize(bytes as u64));
+
+        }
+
+        // Splits "1.5h30m" into ("1.5", "h", "30m").
+        fn split_quantity(text: &str) -> (&str, &str, &str) {
+
+            let num_end = text.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(text.len());
+            let rest = &text[num_end..];
+            let unit_end = rest.find(|c: char| c.is_ascii_digit() || c == '.').unwrap_or(rest.len());
+
+            return (&text[..num_end], rest[..unit_end].trim(), rest[unit_end..].trim_start());
+
+        }
+
+        impl Convert<'_,Duration> for Value {
+
+            fn make(&self) -> Attempt<'_,Duration> {
+
+                match self {
+
+                    Value::String(text) => parse_duration(text),
+
+                    Value::Number(_) => match (as_integer(self), self.as_f64()) {
+
+                        (Some(ms), _) if ms >= 0 => match u64::try_from(ms) {
+
+                            Ok(ms) => Ok(Duration::from_millis(ms)),
+                            Err(_) => fail(format!("json_io::Convert - Duration of {} ms is too long", self)),
+
+                        },
+
+                        (None, Some(ms)) if ms >= 0.0 => match Duration::try_from_secs_f64(ms / 1000.0) {
+
+                            Ok(duration) => Ok(duration),
+                            Err(_) => fail(format!("json_io::Convert - Duration of {} ms is too long", self)),
+
+                        },
+
+                        _ => fail(format!("json_io::Convert - Duration {} must not be negative", self)),
+
+                    },
+
+                    _ => fail(format!("json_io::Convert - Value {} is not a duration (expected milliseconds or a string like \"1m30s\")", self)),
+
+                }
+
+            }
+
+        }
+
+        impl Convert<'_,ByteSize> for Value {
+
+            fn make(&self) -> Attempt<'_,ByteSize> {
+
+                match self {
+
+                    Value::String(text) => parse_size(text),
+                    Value::Number(_) => self.make().map(ByteSize),
+                    _ => fail(format!("json_io::Convert - Value {} is not a size (expected bytes or a string like \"10MiB\")", self)),
+
+                }
+
+            }
+
+        }
+
+        impl Convert<'_,IpAddr> for Value {
+
+            fn make(&self) -> Attempt<'_,IpAddr> {
+
+                let text: &str = self.make()?;
+
+                text.parse().ok().attempt(format!("json_io::Convert - Value {} is not an IP address", self))
+
+            }
+
+        }
+
+        impl Convert<'_,SocketAddr> for Value {
+
+            fn make(&self) -> Attempt<'_,SocketAddr> {
+
+                let text: &str = self.make()?;
+
+                text.parse().ok().attempt(format!("json_io::Convert - Value {} is not a socket address (expected ip:port)", self))
+
+            }
+
+        }
+
+        impl Convert<'_,PathBuf> for Value {
+
+            fn make(&self) -> Attempt<'_,PathBuf> {
+
+                let text: &str = self.make()?;
+
+                if text.is_empty() {return fail("json_io::Convert - Path must not be empty");}
+
+                return Ok(PathBuf::from(text));
+
+            }
+
+        }
+
+        impl std::ops::Deref for ByteSize {type Target = u64; fn deref(&self) -> &u64 {&self.0}}
+
+        #[cfg(test)]
+        mod tests {
+
+            use super::*;
+            use serde_json::json;
+
+            fn duration(val: Value) -> Attempt<'static,Duration> {val.make().map_err(|err| err.into_owned())}
+
+            #[test]
+            fn durations_parse() {
+
+                assert_eq!(parse_duration("250").unwrap(), Duration::from_millis(250));
+                assert_eq!(parse_duration("500ms").unwrap(), Duration::from_millis(500));
+                assert_eq!(parse_duration("1m30s").unwrap(), Duration::from_secs(90));
+                assert_eq!(parse_duration(" 1.5h 2m ").unwrap(), Duration::from_secs(5_520));
+                assert_eq!(parse_duration("3us").unwrap(), Duration::from_micros(3));
+                assert_eq!(parse_duration("1d").unwrap(), Duration::from_secs(86_400));
+
+            }
+
+            #[test]
+            fn durations_reject_bad_input() {
+
+                for text in ["", "s", "2x", "1.2.3s", "-5s"] {
+
+                    assert!(parse_duration(text).is_err(), "{:?} should not parse", text);
+
+                }
+
+            }
+
+            #[test]
+            fn durations_reject_overflow() {
+
+                // Each term fits in u128 nanoseconds, their sum doesn't
+                assert!(parse_duration("2000000000000000000000000d2000000000000000000000000d").is_err());
+
+                // Fits in u128 nanoseconds, not in u64 seconds
+                assert!(parse_duration("1000000000000000000000000d").is_err());
+
+            }
+
+            #[test]
+            fn durations_from_numbers() {
+
+                assert_eq!(duration(json!(1500)).unwrap(), Duration::from_millis(1500));
+                assert_eq!(duration(json!(1.5)).unwrap(), Duration::from_micros(1500));
+                assert_eq!(duration(json!("2s")).unwrap(), Duration::from_secs(2));
+
+                assert!(duration(json!(-1)).is_err());
+                assert!(duration(json!(-0.5)).is_err());
+                assert!(duration(json!(1e300)).is_err());
+                assert!(duration(json!(true)).is_err());
+
+            }
+
+            #[test]
+            fn sizes_parse() {
+
+                assert_eq!(*parse_size("512").unwrap(), 512);
+                assert_eq!(*parse_size("64KB").unwrap(), 64_000);
+                assert_eq!(*parse_size("10MiB").unwrap(), 10 << 20);
+                assert_eq!(*parse_size("1.5 kb").unwrap(), 1_500);
+                assert_eq!(*parse_size("2gib").unwrap(), 2 << 30);
+
+            }
+
+            #[test]
+            fn sizes_reject_bad_input() {
+
+                for text in ["", "0.5b", "10XB", "1MB2", "-1KB", "20000000TB"] {
+
+                    assert!(parse_size(text).is_err(), "{:?} should not parse", text);
+
+                }
+
+            }
+
+        }
+
+    }
+
+    mod path {
+
+        use crate::tools::*;
+        use serde_json::Value;
+
+        #[derive(Clone)] pub enum Segment {Key(String), Index(usize)}
+        #[derive(Clone)] pub struct Path {segments: Vec<Segment>}
+
+        pub trait ToPath {fn to_path<'a>(&self) -> Attempt<'a,Path>;}
+
+        impl Path {
+
+            /// Parses an RFC 6901 JSON Pointer. The empty string refers to the whole document.
+            pub fn from_pointer<'a>(pointer: &str) -> Attempt<'a,Self> {
+
+                if pointer.is_empty() {return Ok(Path::root());}
+
+                let tokens = pointer.strip_prefix('/')
+                    .attempt(format!("json_io::Path - JSON Pointer must start with '/': {:?}", pointer))?;
+
+                let mut segments = Vec::new();
+
+                for token in tokens.split('/') {
+
+                    let mut key = String::with_capacity(token.len());
+                    let mut chars = token.chars();
+
+                    while let Some(c) = chars.next() {
+
+                        if c != '~' {key.push(c); continue;}
+
+                        match chars.next() {
+
+                            Some('0') => key.push('~'),
+                            Some('1') => key.push('/'),
+                            _ => return fail(format!("json_io::Path - Invalid escape in token {:?} of JSON Pointer {:?}", token, pointer)),
+
+                        }
+
+                    }
+
+                    segments.push(Segment::Key(key));
+
+                }
+
+                return Ok(Path {segments});
+
+            }
+
+            /// Parses a dotted path such as `network_args.peers[2].ip`. The empty string refers to the whole document.
+            pub fn from_dotted<'a>(dotted: &str) -> Attempt<'a,Self> {
+
+                let mut segments = Vec::new();
+                let mut rest = dotted;
+
+                while !rest.is_empty() {
+
+                    if let Some(after) = rest.strip_prefix('[') {
+
+                        let end = after.find(']')
+                            .attempt(format!("json_io::Path - Unclosed '[' in path {:?}", dotted))?;
+
+                        let index = after[..end].parse::<usize>().ok()
+                            .attempt(format!("json_io::Path - Invalid index [{}] in path {:?}", &after[..end], dotted))?;
+
+                        segments.push(Segment::Index(index));
+
+                        rest = &after[end + 1..];
+
+                        match rest.strip_prefix('.') {
+
+                            Some(next) if next.is_empty() => return fail(format!("json_io::Path - Trailing '.' in path {:?}", dotted)),
+                            Some(next) => rest = next,
+                            None if rest.is_empty() || rest.starts_with('[') => {},
+                            None => return fail(format!("json_io::Path - Expected '.' or '[' after [{}] in path {:?}", index, dotted)),
+
+                        }
+
+                        continue;
+
+                    }
+
+                    let end = rest.find(|c| c == '.' || c == '[').unwrap_or(rest.len());
+
+                    if end == 0 {return fail(format!("json_io::Path - Empty key in path {:?}", dotted));}
+
+                    segments.push(Segment::Key(rest[..end].to_string()));
+
+                    rest = &rest[end..];
+
+                    if let Some(next) = rest.strip_prefix('.') {
+
+                        if next.is_empty() {return fail(format!("json_io::Path - Trailing '.' in path {:?}", dotted));}
+
+                        rest = next;
+
+                    }
+
+                }
+
+                return Ok(Path {segments});
+
+            }
+
+            pub fn resolve<'a>(&self, root: &'a Value) -> Attempt<'a,&'a Value> {
+
+                let mut node = root;
+
+                for (i, seg) in self.segments.iter().enumerate() {
+
+                    node = match (seg, node) {
+
+                        (Segment::Key(key), Value::Object(map)) => map.get(key).attempt(format!(
+                            "JSON::get - Key {:?} not found at '{}'", key, self.pointer(i)
+                        ))?,
+
+                        (Segment::Key(key), Value::Array(ary)) => {
+
+                            let index = array_index(key).attempt(format!(
+                                "JSON::get - Segment {:?} is not a valid array index at '{}'", key, self.pointer(i)
+                            ))?;
+
+                            ary.get(index).attempt(format!(
+                                "JSON::get - Index {} out of bounds (length {}) at '{}'", index, ary.len(), self.pointer(i)
+                            ))?
+
+                        },
+
+                        (Segment::Index(index), Value::Array(ary)) => ary.get(*index).attempt(format!(
+                            "JSON::get - Index {} out of bounds (length {}) at '{}'", index, ary.len(), self.pointer(i)
+                        ))?,
+
+                        (seg, other) => return fail(format!(
+                            "JSON::get - Cannot resolve segment '{}' at '{}', value there is {}", seg, self.pointer(i), kind(other)
+                        )),
+
+                    };
+
+                }
+
+                return Ok(node);
+
+            }
+
+            /// The JSON Pointer made of the first `len` segments.
+            pub fn pointer(&self, len: usize) -> String {
+
+                let mut out = String::new();
+
+                for seg in self.segments.iter().take(len) {
+
+                    out += "/";
+
+                    match seg {
+
+                        Segment::Key(key) => out += &key.replace('~', "~0").replace('/', "~1"),
+                        Segment::Index(index) => out += &index.to_string(),
+
+                    }
+
+                }
+
+                return out;
+
+            }
+
+            pub fn segments(&self) -> &[Segment] {&self.segments}
+
+            pub fn push(&mut self, seg: Segment) {self.segments.push(seg);}
+
+            pub fn root() -> Self {Path {segments: Vec::new()}}
+
+            pub fn split_last(&self) -> Option<(Path, &Segment)> {
+
+                let (last, parent) = self.segments.split_last()?;
+
+                return Some((Path {segments: parent.to_vec()}, last));
+
+            }
+
+        }
+
+        // RFC 6901: array indices are decimal digits without leading zeros.
+        pub(super) fn array_index(token: &str) -> Option<usize> {
+
+            if token.is_empty() || (token.len() > 1 && token.starts_with('0')) {return None;}
+
+            if !token.bytes().all(|b| b.is_ascii_digit()) {return None;}
+
+            return token.parse().ok();
+
+        }
+
+        pub(super) fn kind(val: &Value) -> &'static str {
+
+            match val {
+
+                Value::Null => "null",
+                Value::Bool(_) => "a boolean",
+                Value::Number(_) => "a number",
+                Value::String(_) => "a string",
+                Value::Array(_) => "an array",
+                Value::Object(_) => "an object",
+
+            }
+
+        }
+
+        impl std::fmt::Display for Segment {
+
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+
+                match self {Segment::Key(key) => write!(f, "{}", key), Segment::Index(index) => write!(f, "[{}]", index)}
+
+            }
+
+        }
+
+        impl std::fmt::Display for Path {
+
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {write!(f, "{}", self.pointer(self.segments.len()))}
+
+        }
+
+        impl ToPath for str {
+
+            fn to_path<'a>(&self) -> Attempt<'a,Path> {
+
+                if self.starts_with('/') {Path::from_pointer(self)} else {Path::from_dotted(self)}
+
+            }
+
+        }
+
+        impl ToPath for String {fn to_path<'a>(&self) -> Attempt<'a,Path> {self.as_str().to_path()}}
+
+        impl ToPath for [&str] {
+
+            fn to_path<'a>(&self) -> Attempt<'a,Path> {
+
+                Ok(Path {segments: self.iter().map(|key| Segment::Key(key.to_string())).collect()})
+
+            }
+
+        }
+
+        impl<const N: usize> ToPath for [&str; N] {fn to_path<'a>(&self) -> Attempt<'a,Path> {self.as_slice().to_path()}}
+
+        impl ToPath for Path {fn to_path<'a>(&self) -> Attempt<'a,Path> {Ok(self.clone())}}
+
+        impl<T: ToPath + ?Sized> ToPath for &T {fn to_path<'a>(&self) -> Attempt<'a,Path> {(**self).to_path()}}
+
+        #[cfg(test)]
+        mod tests {
+
+            use super::*;
+            use serde_json::json;
+
+            #[test]
+            fn pointer_unescapes_tokens() {
+
+                let path = Path::from_pointer("/a~1b/c~0d/0").unwrap();
+
+                let keys: Vec<String> = path.segments().iter().map(|seg| seg.to_string()).collect();
+
+                assert_eq!(keys, ["a/b", "c~d", "0"]);
+                assert_eq!(path.to_string(), "/a~1b/c~0d/0");
+
+                assert_eq!(Path::from_pointer("").unwrap().segments().len(), 0);
+                assert_eq!(Path::from_pointer("/").unwrap().to_string(), "/");
+
+            }
+
+            #[test]
+            fn pointer_rejects_bad_input() {
+
+                assert!(Path::from_pointer("a/b").is_err());
+                assert!(Path::from_pointer("/a~2").is_err());
+                assert!(Path::from_pointer("/a~").is_err());
+
+            }
+
+            #[test]
+            fn dotted_reads_keys_and_indices() {
+
+                let path = Path::from_dotted("network_args.peers[2].ip").unwrap();
+
+                assert_eq!(path.to_string(), "/network_args/peers/2/ip");
+                assert!(matches!(path.segments()[2], Segment::Index(2)));
+
+                assert_eq!(Path::from_dotted("grid[1][0]").unwrap().to_string(), "/grid/1/0");
+                assert_eq!(Path::from_dotted("").unwrap().segments().len(), 0);
+
+            }
+
+            #[test]
+            fn dotted_rejects_bad_input() {
+
+                for dotted in ["a..b", "a.", ".a", "a[x]", "a[1", "a[1]b", "a[1].", "a[-1]"] {
+
+                    assert!(Path::from_dotted(dotted).is_err(), "{:?} should not parse", dotted);
+
+                }
+
+            }
+
+            #[test]
+            fn to_path_picks_the_syntax() {
+
+                assert_eq!("/a/b".to_path().unwrap().to_string(), "/a/b");
+                assert_eq!("a.b".to_path().unwrap().to_string(), "/a/b");
+                assert_eq!(["a/b", "c"].to_path().unwrap().to_string(), "/a~1b/c");
+
+            }
+
+            #[test]
+            fn resolve_walks_objects_and_arrays() {
+
+                let doc = json!({"peers": [{"ip": "10.0.0.1"}, {"ip": "10.0.0.2"}], "a/b": {"~": 1}});
+
+                assert_eq!(Path::from_dotted("peers[1].ip").unwrap().resolve(&doc).unwrap(), "10.0.0.2");
+                assert_eq!(Path::from_pointer("/peers/0/ip").unwrap().resolve(&doc).unwrap(), "10.0.0.1");
+                assert_eq!(Path::from_pointer("/a~1b/~0").unwrap().resolve(&doc).unwrap(), 1);
+
+                let err = Path::from_pointer("/peers/01").unwrap().resolve(&doc).unwrap_err();
+
+                assert!(err.msg.contains("not a valid array index"), "{}", err.msg);
+
+                let err = Path::from_dotted("peers[5]").unwrap().resolve(&doc).unwrap_err();
+
+                assert!(err.msg.contains("Index 5 out of bounds (length 2) at '/peers'"), "{}", err.msg);
+
+                let err = Path::from_dotted("peers[0].ip.x").unwrap().resolve(&doc).unwrap_err();
+
+                assert!(err.msg.contains("value there is a string"), "{}", err.msg);
+
+            }
+
+        }
+
+    }
+
+}
+
+mod json_stream {
+
+    use crate::tools::*;
+    use crate::tools::json_io::{Convert, ToPath, deserialize};
+    use serde::de::DeserializeOwned;
+    use serde_json::Value;
+    use std::io::{BufRead, BufReader};
+    use std::fs::File;
+
+    impl RecordStream<BufReader<File>> {
+
+        /// Opens a dataset file holding either one top-level JSON array or JSON Lines (one value per line),
+        /// told apart by the first non-whitespace byte. Records are read one at a time, so memory use is
+        /// bounded by the largest record rather than the file.
+        pub fn open<'a>(file: impl AsRef<std::path::Path>) -> Attempt<'a,Self> {
+
+            let file = file.as_ref();
+
+            return match File::open(file) {
+
+                Ok(handle) => RecordStream::new(BufReader::new(handle)),
+                Err(err) => fail(format!("RecordStream::open - Failed to open {}: {}", file.display(), err)).category(Category::Io),
+
+            };
+
+        }
+
+    }
+
+    impl<R: BufRead> RecordStream<R> {
+
+        pub fn new<'a>(mut reader: R) -> Attempt<'a,Self> {
+
+            let array = skip_whitespace(&mut reader)? == Some(b'[');
+
+            if array {reader.consume(1);}
+
+            return Ok(RecordStream {reader, array, started: false, done: false, index: 0});
+
+        }
+
+        /// Reads `reader` as JSON Lines without looking at the first byte, for records that are arrays.
+        pub fn lines(reader: R) -> Self {RecordStream {reader, array: false, started: false, done: false, index: 0}}
+
+        /// Number of records returned so far.
+        pub fn count(&self) -> usize {self.index}
+
+        fn next_array<'a>(&mut self) -> Attempt<'a,Option<Vec<u8>>> {
+
+            let mut next = skip_whitespace(&mut self.reader)?;
+
+            if next == Some(b']') {
+
+                self.reader.consume(1);
+                self.done = true;
+
+                // A JSON Lines file whose records are arrays also starts with '['
+                if skip_whitespace(&mut self.reader)?.is_some() {
+
+                    return fail("RecordStream - Unexpected data after the top-level array (read JSON Lines of arrays with RecordStream::lines)").category(Category::Parse);
+
+                }
+
+                return Ok(None);
+
+            }
+
+            if self.started {
+
+                if next != Some(b',') {return fail(format!("RecordStream - Expected ',' or ']' after record {}", self.index.saturating_sub(1))).category(Category::Parse);}
+
+                self.reader.consume(1);
+
+                next = skip_whitespace(&mut self.reader)?;
+
+            }
+
+            if next.is_none() {return fail("RecordStream - Unexpected end of file inside the top-level array").category(Category::Parse);}
+
+            self.started = true;
+
+            return Ok(Some(scan_value(&mut self.reader)?));
+
+        }
+
+        fn next_line<'a>(&mut self) -> Attempt<'a,Option<Vec<u8>>> {
+
+            let mut line = Vec::new();
+
+            loop {
+
+                line.clear();
+
+                if self.reader.read_until(b'\n', &mut line)? == 0 {return Ok(None);}
+
+                if line.iter().any(|b| !b.is_ascii_whitespace()) {return Ok(Some(line));}
+
+            }
+
+        }
+
+    }
+
+    impl<R: BufRead> Iterator for RecordStream<R> {
+
+        type Item = Attempt<'static,Record>;
+
+        /// A malformed JSON Lines record is reported and skipped. A malformed array ends the stream,
+        /// as the position of the next record is unknown.
+        fn next(&mut self) -> Option<Self::Item> {
+
+            if self.done {return None;}
+
+            let raw = match if self.array {self.next_array()} else {self.next_line()} {
+
+                Ok(Some(raw)) => raw,
+                Ok(None) => {self.done = true; return None;},
+                Err(err) => {self.done = true; return Some(Err(err));},
+
+            };
+
+            let index = self.index;
+
+            self.index += 1;
+
+            return Some(match serde_json::from_slice(&raw) {
+
+                Ok(value) => Ok(Record {index, value}),
+
+                Err(err) => {
+
+                    if self.array {self.done = true;}
+
+                    fail(format!("RecordStream - Record {}: {}", index, err)).category(Category::Parse)
+
+                },
+
+            });
+
+        }
+
+    }
+
+    impl Record {
+
+        pub fn index(&self) -> usize {self.index}
+
+        pub fn get<'a,T>(&'a self, path: impl ToPath) -> Attempt<'a,T> where Value: Convert<'a,T> {
+
+            let path = path.to_path()?;
+
+            let val = path.resolve(&self.value).map_err(|mut err| {
+
+                err.msg = format!("Record {}: {}", self.index, err.msg).into(); err.with_category(Category::Parse)
+
+            })?;
+
+            return val.make().map_err(|mut err| {
+
+                err.msg = format!("Record {}: {} at '{}'", self.index, err.msg, path).into(); err.with_category(Category::Parse)
+
+            });
+
+        }
+
+        pub fn get_as<'a,T: DeserializeOwned>(&'a self, path: impl ToPath) -> Attempt<'a,T> {
+
+            let path = path.to_path()?;
+
+            return path.resolve(&self.value).and_then(|val| deserialize(val, path.clone(), |_| false)).map_err(|mut err| {
+
+                err.msg = format!("Record {}: {}", self.index, err.msg).into(); err.with_category(Category::Parse)
+
+            });
+
+        }
+
+        pub fn into_value(self) -> Value {self.value}
+
+    }
+
+    impl std::ops::Deref for Record {type Target = Value; fn deref(&self) -> &Value {&self.value}}
+
+    fn skip_whitespace<'a>(reader: &mut impl BufRead) -> Attempt<'a,Option<u8>> {
+
+        loop {
+
+            let buf = reader.fill_buf()?;
+
+            if buf.is_empty() {return Ok(None);}
+
+            match buf.iter().position(|b| !b.is_ascii_whitespace()) {
+
+                Some(pos) => {let byte = buf[pos]; reader.consume(pos); return Ok(Some(byte));},
+                None => {let len = buf.len(); reader.consume(len);},
+
+            }
+
+        }
+
+    }
+
+    // Copies the bytes of one JSON value. Containers end at their matching bracket, scalars at the
+    // next `,`, `]` or whitespace outside a string. The content is validated later by serde.
+    fn scan_value<'a>(reader: &mut impl BufRead) -> Attempt<'a,Vec<u8>> {
+
+        let mut out = Vec::new();
+        let (mut depth, mut in_string, mut escaped) = (0usize, false, false);
+
+        loop {
+
+            let buf = reader.fill_buf()?;
+
+            if buf.is_empty() {return fail("RecordStream - Unexpected end of file inside a record").category(Category::Parse);}
+
+            let mut used = 0;
+
+            for &byte in buf {
+
+                match (in_string, escaped, byte) {
+
+                    (true, true, _) => escaped = false,
+                    (true, false, b'\\') => escaped = true,
+                    (true, false, b'"') => in_string = false,
+                    (true, false, _) => {},
+
+                    (false, _, b'"') => in_string = true,
+                    (false, _, b'{' | b'[') => depth += 1,
+
+                    (false, _, b'}' | b']') if depth > 0 => {
+
+                        depth -= 1;
+
+                        if depth == 0 {out.extend_from_slice(&buf[..=used]); reader.consume(used + 1); return Ok(out);}
+
+                    },
+
+                    (false, _, b',' | b']') if depth == 0 => {out.extend_from_slice(&buf[..used]); reader.consume(used); return Ok(out);},
+                    (false, _, byte) if depth == 0 && byte.is_ascii_whitespace() => {out.extend_from_slice(&buf[..used]); reader.consume(used); return Ok(out);},
+
+                    _ => {},
+
+                }
+
+                used += 1;
+
+            }
+
+            out.extend_from_slice(buf);
+
+            let len = buf.len();
+
+            reader.consume(len);
+
+        }
+
+    }
+
+    #[cfg(test)]
+    mod tests {
+
+        use super::*;
+        use serde_json::json;
+        use std::io::Cursor;
+
+        // A one byte buffer makes every record cross a `fill_buf` boundary.
+        fn stream(text: &str) -> Attempt<'static,RecordStream<BufReader<Cursor<Vec<u8>>>>> {
+
+            return RecordStream::new(BufReader::with_capacity(1, Cursor::new(text.as_bytes().to_vec())));
+
+        }
+
+        fn values(stream: impl Iterator<Item = Attempt<'static,Record>>) -> Vec<Result<Value, String>> {
+
+            return stream.map(|record| record.map(Record::into_value).map_err(|err| err.msg.to_string())).collect();
+
+        }
+
+        #[test]
+        fn scan_value_handles_strings_and_nesting() {
+
+            let text = r#"{"a": "x,]}\"\\", "b": [1, {"c": []}]} , 2"#;
+
+            let mut reader = BufReader::with_capacity(1, Cursor::new(text));
+
+            let raw = scan_value(&mut reader).unwrap();
+
+            assert_eq!(serde_json::from_slice::<Value>(&raw).unwrap(), json!({"a": "x,]}\"\\", "b": [1, {"c": []}]}));
+
+            assert_eq!(skip_whitespace(&mut reader).unwrap(), Some(b','));
+
+            let mut reader = Cursor::new("\"a b\" ]");
+
+            assert_eq!(scan_value(&mut reader).unwrap(), b"\"a b\"");
+
+            assert!(scan_value(&mut Cursor::new("{\"a\": [1, 2}")).is_err());
+
+        }
+
+        #[test]
+        fn reads_a_top_level_array() {
+
+            let records = values(stream(" [ {\"id\": 1}, \"two\", 3.5 , [4], null ] \n").unwrap());
+
+            assert_eq!(records, vec![Ok(json!({"id": 1})), Ok(json!("two")), Ok(json!(3.5)), Ok(json!([4])), Ok(json!(null))]);
+
+            assert!(values(stream("[]").unwrap()).is_empty());
+            assert!(values(stream("").unwrap()).is_empty());
+
+        }
+
+        #[test]
+        fn reads_json_lines() {
+
+            let mut records = stream("{\"id\": 1}\n\n{\"id\": 2\n{\"id\": 3}").unwrap();
+
+            assert_eq!(records.next().unwrap().unwrap().get::<i64>("id").unwrap(), 1);
+
+            // A malformed line is reported and skipped
+            let err = records.next().and_then(Result::err).expect("line 2 should fail");
+
+            assert!(err.msg.starts_with("RecordStream - Record 1:"), "{}", err.msg);
+
+            let last = records.next().unwrap().unwrap();
+
+            assert_eq!((last.index(), last.get::<i64>("id").unwrap()), (2, 3));
+            assert!(records.next().is_none());
+            assert_eq!(RecordStream::count(&records), 3);
+
+        }
+
+        #[test]
+        fn array_errors_end_the_stream() {
+
+            let records = values(stream("[1, 2 3]").unwrap());
+
+            assert_eq!(records.len(), 3);
+            assert!(records[2].as_ref().is_err_and(|err| err.contains("Expected ',' or ']' after record 1")));
+
+            let records = values(stream("[1, {\"a\": ").unwrap());
+
+            assert!(records.last().unwrap().as_ref().is_err_and(|err| err.contains("end of file")));
+
+        }
+
+        #[test]
+        fn arrays_of_arrays_need_lines() {
+
+            let records = values(stream("[1,2]\n[3,4]\n").unwrap());
+
+            assert!(records.last().unwrap().as_ref().is_err_and(|err| err.contains("Unexpected data after the top-level array")));
+
+            let records = values(RecordStream::lines(Cursor::new("[1,2]\n[3,4]\n")));
+
+            assert_eq!(records, vec![Ok(json!([1, 2])), Ok(json!([3, 4]))]);
+
+        }
+
+    }
+
+}
+
+mod reporting {
+
+    use crate::tools::*;
+    use std::io::IsTerminal;
+    use std::sync::RwLock;
+
+    static REPORTER: RwLock<Option<Box<dyn Reporter>>> = RwLock::new(None);
+
+    /// Installs the reporter every `Fail::emit` goes through, for all threads.
+    pub fn set_reporter(reporter: impl Reporter + 'static) {
+
+        *REPORTER.write().unwrap_or_else(|poison| poison.into_inner()) = Some(Box::new(reporter));
+
+    }
+
+    /// Picks a reporter by name at startup: `log`, `terminal` or `json`.
+    pub fn select_reporter<'a>(name: &str) -> Attempt<'a,()> {
+
+        match name {
+
+            "log" => set_reporter(LogReporter),
+            "terminal" => set_reporter(TerminalReporter::new()),
+            "json" => set_reporter(JsonReporter),
+            _ => return fail(format!("select_reporter - Unknown reporter '{}', expected log, terminal or json", name)).category(Category::Config),
+
+        }
+
+        return Ok(());
+
+    }
+
+    impl Fail<'_> {
+
+        /// Hands the failure to the installed reporter. Without one, failures go to the error log if
+        /// an `IOManager` is installed and to the terminal otherwise.
+        pub fn emit(&self) {
+
+            let reporter = REPORTER.read().unwrap_or_else(|poison| poison.into_inner());
+
+            match reporter.as_ref() {
+
+                Some(reporter) => reporter.emit(self),
+                None => LogReporter.emit(self),
+
+            }
+
+        }
+
+    }
+
+    impl Reporter for LogReporter {
+
+        fn emit(&self, fail: &Fail) {
+
+            let logged = with_logger(|mng| {mng.push_stderr(fail.show());}).is_some();
+
+            if !logged {TerminalReporter::new().emit(fail);}
+
+        }
+
+    }
+
+    impl TerminalReporter {
+
+        /// Colors the output when stderr is a terminal and `NO_COLOR` is not set.
+        pub fn new() -> Self {
+
+            return TerminalReporter {color: std::io::stderr().is_terminal() && std::env::var_os("NO_COLOR").is_none()};
+
+        }
+
+        pub fn with_color(color: bool) -> Self {TerminalReporter {color}}
+
+    }
+
+    impl Reporter for TerminalReporter {fn emit(&self, fail: &Fail) {eprintln!("{}", fail.render(self.color));}}
+
+    impl Reporter for JsonReporter {fn emit(&self, fail: &Fail) {fail.print_report().log_report();}}
+
+}
+
+mod redaction {
+
+    use crate::tools::STR;
+    use std::sync::Mutex;
+
+    const MASK: &str = "***";
+
+    // Shorter values would mask unrelated text, such as digits in line numbers.
+    const MIN_SECRET_LEN: usize = 4;
+
+    static SECRETS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+    /// Registers a value that must never be written out, e.g. a password read from the config.
+    pub fn add_secret(secret: impl Into<String>) {
+
+        let secret = secret.into();
+
+        if secret.len() < MIN_SECRET_LEN {return;}
+
+        let mut secrets = SECRETS.lock().unwrap_or_else(|poison| poison.into_inner());
+
+        if secrets.contains(&secret) {return;}
+
+        secrets.push(secret);
+
+        // Longest first, so a secret containing another one is masked as a whole.
+        secrets.sort_by(|a, b| b.len().cmp(&a.len()));
+
+    }
+
+    /// Masks every registered secret in `text`. Text without secrets is returned as is.
+    pub fn redact(text: STR<'_>) -> STR<'_> {
+
+        let secrets = SECRETS.lock().unwrap_or_else(|poison| poison.into_inner());
+
+        if !secrets.iter().any(|secret| text.contains(secret.as_str())) {return text;}
+
+        let mut out = text.into_owned();
+
+        for secret in secrets.iter() {out = out.replace(secret.as_str(), MASK);}
+
+        return out.into();
+
+    }
+
+}
+
+mod failure {
+
+    use crate::tools::{Fail, Attempt, STR, Category, Frame};
+    use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+    use std::backtrace::Backtrace;
+    use std::sync::OnceLock;
+    use std::io::Write;
+    use std::cell::Cell;
+
+    pub trait ExtLocation {fn as_place<'a>(&'a self) -> ErrDetails<'a>;}
+    pub struct ErrDetails<'a> {file: STR<'a>, line: u32, column: u32, function: String, note: Option<STR<'a>>}
+    pub struct Trace {captured: Option<Backtrace>, frames: OnceLock<Vec<Frame>>}
+    
+    #[allow(unused)]
+    pub mod fail {
+
+        use crate::tools::redact;
+        use super::*;
+        use core::panic;
+        use std::panic::Location;
+        use std::fmt::Debug;
+
+        impl<'a> Fail<'a> {
+
+            pub fn new(place: &'a Location<'a>, msg: impl Into<STR<'a>>) -> Self {
+
+                return Fail {places: vec![place.as_place()], msg: redact(msg.into()), cause: None, category: Category::Internal, code: None, time: now(), trace: Trace::capture()};
+
+            }
+
+            /// Adds an outer frame to the chain, `places[0]` stays the origin of the failure.
+            pub fn context(mut self, place: &'a Location<'a>, note: impl Into<STR<'a>>) -> Self {
+
+                let mut detail = place.as_place();
+
+                detail.note = Some(redact(note.into()));
+
+                self.places.push(detail);
+
+                return self;
+
+            }
+
+            #[track_caller] 
+            pub fn panic<T>(msg: impl Into<STR<'a>>) -> T {
+
+                <Attempt<'a,T>>::Err(Fail::new(std::panic::Location::caller(), msg)).unwrap();
+
+                unreachable!("tools::panic - Unreachable code reached.");
+
+            }
+
+            pub fn from_debug(place: &'a Location<'a>, err: impl Debug) -> Self {
+
+                return Fail {places: vec![place.as_place()], msg: redact(format!("{:?}", err).into()), cause: None, category: Category::Internal, code: None, time: now(), trace: Trace::capture()};
+
+            }
+
+            /// Same message as `from_debug`, but keeps the original error around for `source()`.
+            pub fn from_error(place: &'a Location<'a>, err: impl std::error::Error + Send + Sync + 'static) -> Self {
+
+                let mut fail = Fail::from_debug(place, &err);
+
+                fail.cause = Some(Box::new(err));
+
+                return fail;
+
+            }
+
+            /// Keeps an earlier failure this one follows from, shown under "Caused by" and returned by `source()`.
+            pub fn with_cause(mut self, cause: Fail<'static>) -> Self {self.cause = Some(Box::new(cause)); return self;}
+
+            /// The earlier failure passed to `with_cause`, if any.
+            pub fn caused_by(&self) -> Option<&Fail<'static>> {
+
+                return self.cause.as_deref().and_then(|cause| cause.downcast_ref::<Fail<'static>>());
+
+            }
+
+            /// Detaches the failure from the data it borrowed so it can be stored or sent across threads.
+            pub fn into_owned(self) -> Fail<'static> {
+
+                let places = self.places.into_iter().map(ErrDetails::into_owned).collect();
+
+                return Fail {places, msg: self.msg.into_owned().into(), cause: self.cause, category: self.category, code: self.code, time: self.time, trace: self.trace};
+
+            }
+
+            pub fn with_category(mut self, category: Category) -> Self {self.category = category; return self;}
+
+            pub fn with_code(mut self, code: impl Into<STR<'static>>) -> Self {self.code = Some(code.into()); return self;}
+
+            pub fn category(&self) -> Category {self.category}
+
+            pub fn code(&self) -> Option<&str> {self.code.as_deref()}
+
+            /// The failure as text. Only formats, use `Fail::emit` to hand it to the reporter.
+            pub fn show(&self) -> String {return self.render(false);}
+
+            /// Same layout as `show`, with ANSI colors when `color` is set.
+            pub fn render(&self, color: bool) -> String {
+
+                let paint = |code: &str, text: String| if color {format!("\x1b[{}m{}\x1b[0m", code, text)} else {text};
+
+                let header = match &self.code {
+
+                    Some(code) => format!("Error [{} {}]:", self.category, code),
+                    None => format!("Error [{}]:", self.category),
+
+                };
+
+                let mut out = format!("\n\t{} {}", paint("1;31", header), self.msg);
+
+                let snippets = super::snippets_enabled();
+
+                // Innermost first, each context frame then reads as "while ..." on top of it
+                for (i, place) in self.places.iter().enumerate() {
+                    
+                    out += &format!("\n\t{}\n", paint("2", format!("{} - {}, {}, {}", i, place.file, place.line, place.function)));
+
+                    let snippet = if snippets {place.snippet(&paint)} else {None};
+
+                    match (&place.note, snippet) {
+
+                        (Some(note), Some(snippet)) => out += &format!("{}\t{} = {}\n", snippet, " ".repeat(snippet_gutter(place.line)), paint("36", note.to_string())),
+                        (None, Some(snippet)) => out += &snippet,
+                        (Some(note), None) => out += &format!("\t    {}\n", paint("36", note.to_string())),
+                        (None, None) => {},
+
+                    }
+
+                }
+
+                if let Some(cause) = self.caused_by() {
+
+                    out += &format!("\n\t{}", paint("1", "Caused by:".to_string()));
+                    out += &cause.render(color).replace("\n\t", "\n\t\t");
+
+                }
+
+                let trace = self.backtrace();
+
+                if !trace.is_empty() {out += "\n\tBacktrace:\n";}
+
+                for (i, frame) in trace.iter().enumerate() {
+
+                    // Collapsed runs of std/dependency frames have no file
+                    if frame.file.is_empty() {out += &format!("\t    {}\n", paint("2", frame.function.clone())); continue;}
+
+                    out += &format!("\t  {} - {}, {}, {}\n", i, frame.file, frame.line, frame.function);
+
+                }
+
+                return out;
+
+            }
+    
+        }
+
+        impl std::fmt::Debug for Fail<'_> {fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {write!(f, "{}", self.show())}}
+        impl std::fmt::Display for Fail<'_> {fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {write!(f, "{}", self.show())}}
+
+        // Seconds since the epoch, the same clock the log lines use.
+        fn now() -> u64 {
+
+            return std::time::SystemTime::now()
+                .duration_since(std::time::SystemTime::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+
+        }
+
+        impl std::error::Error for Fail<'_> {
+
+            fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+
+                return self.cause.as_deref().map(|err| err as &(dyn std::error::Error + 'static));
+
+            }
+
+        }
+
+        impl<'a> ErrDetails<'a> {
+
+            fn into_owned(self) -> ErrDetails<'static> {
+
+                let note = self.note.map(|note| note.into_owned().into());
+
+                return ErrDetails {file: self.file.into_owned().into(), line: self.line, column: self.column, function: self.function, note};
+
+            }
+
+        }
+
+        impl<'a> From<&'a str> for Fail<'a> {#[track_caller] fn from(msg: &'a str) -> Self {Fail::new(Location::caller(), msg)}}
+        impl From<String> for Fail<'_> {#[track_caller] fn from(msg: String) -> Self {Fail::new(Location::caller(), msg)}}
+
+        impl From<std::net::AddrParseError> for Fail<'_> {#[track_caller] fn from(err: std::net::AddrParseError) -> Self {Fail::from_error(Location::caller(), err).with_category(Category::Parse)}}
+
+        impl From<std::io::Error> for Fail<'_> {#[track_caller] fn from(err: std::io::Error) -> Self {Fail::from_error(Location::caller(), err).with_category(Category::Io)}}
+
+        impl From<serde_json::Error> for Fail<'_> {#[track_caller] fn from(err: serde_json::Error) -> Self {Fail::from_error(Location::caller(), err).with_category(Category::Parse)}}
+
+        impl From<std::string::FromUtf8Error> for Fail<'_> {#[track_caller] fn from(err: std::string::FromUtf8Error) -> Self {Fail::from_error(Location::caller(), err).with_category(Category::Parse)}}
+
+    }
+
+    mod caller {
+
+        use super::{ExtLocation, ErrDetails, STR};
+        use std::panic::Location;
+
+        // FN_INDEX: (file, (line, column) of the start, of the end, qualified name) of every function and closure,
+        // SOURCES: (file, text) of every source file. Both written by build.rs
+        include!(concat!(env!("OUT_DIR"), "/fn_index.rs"));
+
+        // Locations outside the index, e.g. in dependency crates or outside any function.
+        const UNKNOWN: &str = "{{unknown}}";
+
+        /// The qualified name of the innermost function or closure of this crate holding `at` (line, column).
+        pub(super) fn function_at(file: &str, at: (u32, u32)) -> Option<&'static str> {
+
+            // Ranges only nest, so of all the ranges holding the location the innermost starts last
+            return FN_INDEX.iter()
+                .filter(|(name, first, last, _)| *name == file && (*first..=*last).contains(&at))
+                .max_by_key(|(_, first, ..)| *first)
+                .map(|(.., function)| *function);
+
+        }
+
+        /// The `FN_INDEX` name of a file as a backtrace prints it: `./src/...` when run from the crate
+        /// root, an absolute path otherwise. None for files outside this crate.
+        pub(super) fn crate_file(path: &str) -> Option<&'static str> {
+
+            let path = path.trim_start_matches("./");
+
+            let rel = path.strip_prefix(env!("CARGO_MANIFEST_DIR")).map(|rel| rel.trim_start_matches('/')).unwrap_or(path);
+
+            // The suffix match covers a crate built in one place and run from a copy elsewhere
+            return SOURCES.iter().map(|(name, _)| *name).find(|name| rel == *name || rel.ends_with(&format!("/{}", name)));
+
+        }
+
+        pub(super) fn source(file: &str) -> Option<&'static str> {
+
+            return SOURCES.iter().find(|(name, _)| *name == file).map(|(_, src)| *src);
+
+        }
+
+        impl ExtLocation for Location<'_> {
+            
+            fn as_place<'a>(&'a self) -> ErrDetails<'a> {
+
+                let file: STR = self.file().to_string().into();
+                let line: u32 = self.line();
+                let at = (line, self.column());
+
+                let function = function_at(&file, at).unwrap_or(UNKNOWN).to_string();
+
+                return ErrDetails {file, line, column: self.column(), function, note: None};
+    
+            }
+        
+        }
+
+    }
+
+    static SNIPPETS: AtomicBool = AtomicBool::new(true);
+
+    /// Turns the source snippets under each frame of `Fail::show` on or off, e.g. off for production logs.
+    pub fn set_snippets(enabled: bool) {SNIPPETS.store(enabled, Ordering::Relaxed);}
+
+    fn snippets_enabled() -> bool {SNIPPETS.load(Ordering::Relaxed)}
+
+    fn snippet_gutter(line: u32) -> usize {(line + SNIPPET_CONTEXT).to_string().len() + 1}
+
+    // Source lines shown above and below the failing one.
+    const SNIPPET_CONTEXT: u32 = 1;
+
+    static BACKTRACE: AtomicU8 = AtomicU8::new(BACKTRACE_ENV);
+
+    const BACKTRACE_ENV: u8 = 0;
+    const BACKTRACE_ON: u8 = 1;
+    const BACKTRACE_OFF: u8 = 2;
+
+    /// Captures a backtrace with every new `Fail`, or never. Until this is called capture follows
+    /// `RUST_BACKTRACE`/`RUST_LIB_BACKTRACE`, like `Backtrace::capture`.
+    pub fn set_backtrace(enabled: bool) {BACKTRACE.store(if enabled {BACKTRACE_ON} else {BACKTRACE_OFF}, Ordering::Relaxed);}
+
+    thread_local! {static QUIET: Cell<u32> = const {Cell::new(0)};}
+
+    /// Runs `f` without capturing backtraces on this thread, for failures that are expected and thrown
+    /// away, such as probing every address of the LAN or a `get_or` falling back to its default.
+    pub fn without_backtrace<R>(f: impl FnOnce() -> R) -> R {
+
+        struct Quiet;
+
+        impl Drop for Quiet {fn drop(&mut self) {QUIET.with(|depth| depth.set(depth.get() - 1));}}
+
+        QUIET.with(|depth| depth.set(depth.get() + 1));
+
+        let _quiet = Quiet;
+
+        return f();
+
+    }
+
+    mod trace {
+
+        use super::{Trace, Fail, Frame, Backtrace, OnceLock, BACKTRACE, BACKTRACE_ON, BACKTRACE_OFF, QUIET, Ordering};
+        use std::cell::Cell;
+        use std::backtrace::BacktraceStatus;
+
+        impl Trace {
+
+            // Boxed, an inline `Backtrace` would make every `Attempt` 80 bytes larger.
+            pub(super) fn capture() -> Option<Box<Self>> {
+
+                if QUIET.with(Cell::get) > 0 {return None;}
+
+                let captured = match BACKTRACE.load(Ordering::Relaxed) {
+
+                    BACKTRACE_ON => Backtrace::force_capture(),
+                    BACKTRACE_OFF => return None,
+                    _ => Backtrace::capture(),
+
+                };
+
+                if captured.status() != BacktraceStatus::Captured {return None;}
+
+                return Some(Box::new(Trace {captured: Some(captured), frames: OnceLock::new()}));
+
+            }
+
+            /// Captures regardless of `RUST_BACKTRACE`, unless turned off with `set_backtrace(false)`.
+            pub(super) fn force() -> Option<Box<Self>> {
+
+                if BACKTRACE.load(Ordering::Relaxed) == BACKTRACE_OFF {return None;}
+
+                return Some(Box::new(Trace {captured: Some(Backtrace::force_capture()), frames: OnceLock::new()}));
+
+            }
+
+            pub(super) fn resolved(frames: Vec<Frame>) -> Option<Box<Self>> {
+
+                if frames.is_empty() {return None;}
+
+                return Some(Box::new(Trace {captured: None, frames: OnceLock::from(frames)}));
+
+            }
+
+        }
+
+        impl Fail<'_> {
+
+            /// The backtrace taken when the failure was created, starting at the frame of its first place.
+            /// Frames of this crate are named through the function index, runs of frames from std and
+            /// dependencies such as rayon are collapsed into one line each.
+            pub fn backtrace(&self) -> &[Frame] {
+
+                let Some(trace) = &self.trace else {return &[];};
+
+                let origin = self.places.first().map(|place| (place.file.as_ref(), place.line));
+
+                return trace.frames.get_or_init(|| match &trace.captured {
+
+                    Some(captured) => resolve(captured, origin),
+                    None => Vec::new(),
+
+                });
+
+            }
+
+        }
+
+        // Symbol and "at file:line:column" of each frame, from the `Display` output of `Backtrace`.
+        fn parse(captured: &Backtrace) -> Vec<(String, Option<(String, u32, u32)>)> {
+
+            let text = captured.to_string();
+            let mut frames: Vec<(String, Option<(String, u32, u32)>)> = Vec::new();
+
+            for line in text.lines().map(str::trim) {
+
+                if let Some(at) = line.strip_prefix("at ") {
+
+                    let mut parts = at.rsplitn(3, ':');
+
+                    let (Some(column), Some(num), Some(file)) = (parts.next(), parts.next(), parts.next()) else {continue;};
+
+                    if let (Some(frame), Ok(num), Ok(column)) = (frames.last_mut(), num.parse(), column.parse()) {
+
+                        let file = super::caller::crate_file(file).map(String::from).unwrap_or_else(|| file.to_string());
+
+                        frame.1 = Some((file, num, column));
+
+                    }
+
+                } else if let Some((_, symbol)) = line.split_once(": ") {
+
+                    frames.push((symbol.to_string(), None));
+
+                }
+
+            }
+
+            return frames;
+
+        }
+
+        fn resolve(captured: &Backtrace, origin: Option<(&str, u32)>) -> Vec<Frame> {
+
+            let frames = parse(captured);
+
+            // Everything above the place the failure was created is `Fail` machinery
+            let start = frames.iter()
+                .position(|(_, at)| matches!((at, origin), (Some((file, line, _)), Some(origin)) if (file.as_str(), *line) == origin))
+                .unwrap_or(0);
+
+            let mut out = Vec::new();
+            let mut skipped: Vec<String> = Vec::new();
+            let mut count = 0;
+
+            for (symbol, at) in frames.into_iter().skip(start) {
+
+                let own = at.as_ref().and_then(|(file, line, column)| {
+
+                    super::caller::function_at(file, (*line, *column)).map(|function| (file.clone(), *line, *column, function))
+
+                });
+
+                match own {
+
+                    Some((file, line, column, function)) => {
+
+                        collapse(&mut out, &mut skipped, &mut count);
+
+                        out.push(Frame {file, line, column, function: function.to_string(), context: None});
+
+                    },
+
+                    None => {
+
+                        let krate = crate_of(&symbol);
+
+                        if !skipped.contains(&krate) {skipped.push(krate);}
+
+                        count += 1;
+
+                    },
+
+                }
+
+            }
+
+            collapse(&mut out, &mut skipped, &mut count);
+
+            return out;
+
+        }
+
+        // The identifier before the first `::`, so `<alloc::boxed::Box<F> as ...>` and `<fn() -> core::...>` name their crate.
+        fn crate_of(symbol: &str) -> String {
+
+            let Some(end) = symbol.find("::") else {return symbol.trim_matches(|c| c == '<' || c == '>').to_string();};
+
+            let start = symbol[..end].rfind(|c: char| !(c.is_alphanumeric() || c == '_')).map(|i| i + 1).unwrap_or(0);
+
+            return symbol[start..end].to_string();
 
-        });
+        }
 
-    }
+        fn collapse(out: &mut Vec<Frame>, skipped: &mut Vec<String>, count: &mut usize) {
 
-}
-pub trait ExtResult<T> {fn unwrap_or_stderr(self) -> T;}
-pub trait MapOption<'a,T>: Sized {
-    
-    fn attempt(self, msg: impl Into<Fail<'a>>) -> Attempt<'a,T> {self.attempt(msg.into())}
+            if *count == 0 {return;}
 
-}
+            let function = format!("... {} frame(s) in {}", count, skipped.join(", "));
 
-// -------------------------------------- Structures & Types -------------------------------------- //
+            out.push(Frame {file: String::new(), line: 0, column: 0, function, context: None});
 
-type STR<'a> = std::borrow::Cow<'a, str>;
-pub type Attempt<'a,T> = Result<T, Fail<'a>>;
+            skipped.clear();
+            *count = 0;
 
-pub struct Fail<'a> {places: Vec<failure::ErrDetails<'a>>, msg: STR<'a>}
-pub struct JSON {root: serde_json::Value}
-pub struct Connection {stream: std::net::TcpStream}
-pub struct IOManager {stderr: std::fs::File, stdout: std::fs::File}
+        }
 
-// ------------------------------------------- Functions ------------------------------------------- //
+    }
 
-#[track_caller]
-pub fn fail<'a,T>(msg: impl Into<std::borrow::Cow<'a,str>>) -> Attempt<'a,T> {
-    
-    return Err(Fail::new(std::panic::Location::caller(), msg));
+    /// Reports every panic, on any thread including rayon workers, as an internal `Fail` through the
+    /// installed reporter (the error log by default), then flushes and exits with the internal error code.
+    pub fn install_panic_hook() {
 
-}
+        std::panic::set_hook(Box::new(|info| {
 
-// -------------------------------------------- Modules -------------------------------------------- //
+            let payload = info.payload();
 
-mod extend_string {
+            let msg = payload.downcast_ref::<&str>().map(|msg| msg.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "non-string panic payload".to_string());
 
-    use crate::tools::ExtString;
+            let thread = std::thread::current();
 
-    impl<T> ExtString for T where T: AsRef<str> {}
+            let name = thread.name().map(String::from).unwrap_or_else(|| format!("{:?}", thread.id()));
 
-}
+            let msg = format!("Thread '{}' panicked: {}", name, msg);
 
-mod json_io {
+            let mut fail = match info.location() {
 
-    use crate::tools::*;
-    use serde_json::Value;
-    use base64::{Engine as _, engine::general_purpose::STANDARD};
+                Some(place) => Fail::new(place, msg),
+                None => Fail::from(msg),
 
+            };
 
-    impl JSON {
+            fail.trace = Trace::force();
 
-        pub fn new<'a>() -> Attempt<'a,Self> {
+            fail.with_category(Category::Internal).with_code("panic").emit();
 
-            let raw = match std::env::args().nth(1) {
+            let _ = std::io::stdout().flush();
+            let _ = std::io::stderr().flush();
 
-                Some(raw) => raw,
-                None => return fail("No JSON provided."),
+            std::process::exit(Category::Internal.exit_code());
 
-            };
+        }));
 
-            // Decode base64 to string
-            let json_str = match STANDARD.decode(&raw) {
+    }
 
-                Ok(bytes) => String::from_utf8(bytes)?,
+    mod snippet {
 
-                _ => raw, // If it's not base64, just use the raw string
+        use super::{ErrDetails, SNIPPET_CONTEXT, snippet_gutter};
 
-            };
+        impl ErrDetails<'_> {
 
-            Ok(JSON {root: match serde_json::from_str(&json_str) {
+            /// The failing line between its neighbours, rustc style, with a caret under the call.
+            /// None when the file is not part of this crate.
+            pub(super) fn snippet(&self, paint: &dyn Fn(&str, String) -> String) -> Option<String> {
 
-                Ok(val) => val,
-                Err(err) => return fail(format!("{:?}. \n\tInput: {}", err, json_str)),
+                let src = super::caller::source(&self.file)?;
+                let lines: Vec<&str> = src.lines().collect();
 
-            }})
+                let line = self.line as usize;
 
-        }
+                if line == 0 || line > lines.len() {return None;}
 
-        pub fn get<'a,T>(&'a self, keys: &[&str]) -> Attempt<'a,T> where Value: Convert<'a,T> {
+                let first = line.saturating_sub(SNIPPET_CONTEXT as usize).max(1);
+                let last = (line + SNIPPET_CONTEXT as usize).min(lines.len());
+                let width = snippet_gutter(self.line);
 
-            match self.root.get(keys[0]) {
+                let mut out = format!("\t{}\n", paint("1;34", format!("{} |", " ".repeat(width))));
 
-                Some(val) => {
+                for num in first..=last {
 
-                    if keys.len() == 1 {return val.make();}
+                    let text = lines[num - 1];
 
-                    let slice = &keys[1..keys.len()];
+                    out += &format!("\t{} {}\n", paint("1;34", format!("{:>w$} |", num, w = width)), text);
 
-                    return self.get(slice);
+                    if num != line {continue;}
+
+                    // The caret covers the called path, e.g. `fail` or `Fail::from`
+                    let start = (self.column as usize).saturating_sub(1);
+                    let len = text.chars().skip(start).take_while(|c| c.is_alphanumeric() || *c == '_' || *c == ':').count().max(1);
+
+                    out += &format!("\t{} {}{}\n", paint("1;34", format!("{} |", " ".repeat(width))), " ".repeat(start), paint("1;31", "^".repeat(len)));
 
                 }
 
-                None => return fail(format!("JSON::get - Key not found: {:?}", keys)),
+                return Some(out);
 
             }
-        
-        }
 
-        pub fn get_or<'a,T>(&'a self, keys: &[&str], default: T) -> T where Value: Convert<'a,T> {self.get(keys).unwrap_or(default)}
+        }
 
     }
 
-    impl std::ops::Deref for JSON {type Target = Value; fn deref(&self) -> &Value {&self.root}}
+    mod report {
 
-    pub trait Convert<'a,T> {fn make(&'a self) -> Attempt<'a,T>;}
+        use super::{Fail, ErrDetails, Attempt, Trace};
+        use crate::tools::{Report, Frame, Category, with_logger};
 
-    impl<'a> Convert<'a,&'a str> for Value {
-        
-        fn make(&'a self) -> Attempt<'a,&str> {
-        
-            self.as_str().attempt(format!("json_io::Convert - Value is not a string: {:?}", self))
-    
-        }
+        impl Fail<'_> {
 
-    }
+            /// The failure as plain data, chain ordered innermost first like `Fail::show`. A cause that
+            /// is not a `Fail`, such as the `io::Error` kept by `Fail::from_error`, becomes a report
+            /// with only its message.
+            pub fn report(&self) -> Report {
 
-    impl Convert<'_,u16> for Value {
-        
-        fn make(&self) -> Attempt<'_,u16> {
-        
-            self.as_u64().map(|x| x as u16).attempt(format!("json_io::Convert - Value is not a u16: {:?}", self))
-    
-        }
+                let chain = self.places.iter().map(|place| Frame {
 
-    }
+                    file: place.file.to_string(),
+                    line: place.line,
+                    column: place.column,
+                    function: place.function.clone(),
+                    context: place.note.as_ref().map(|note| note.to_string()),
 
-    impl Convert<'_,u64> for Value {
-        
-        fn make(&self) -> Attempt<'_,u64> {
-        
-            self.as_u64().attempt(format!("json_io::Convert - Value is not a u64: {:?}", self))
-    
-        }
+                }).collect();
 
-    }
+                return Report {
+                    message: self.msg.to_string(),
+                    category: self.category,
+                    code: self.code.as_ref().map(|code| code.to_string()),
+                    timestamp: self.time,
+                    chain,
+                    backtrace: self.backtrace().to_vec(),
+                    cause: self.cause.as_deref().map(|cause| Box::new(match cause.downcast_ref::<Fail<'static>>() {
 
-}
+                        Some(fail) => fail.report(),
+                        None => foreign(cause, self.category, self.time),
 
-mod failure {
+                    })),
+                };
+
+            }
 
-    use crate::tools::{Fail, Attempt, STR};
+            /// The report as a single JSON line.
+            pub fn to_json(&self) -> String {
 
-    pub trait ExtLocation {fn as_place<'a>(&'a self) -> ErrDetails<'a>;}
-    pub struct ErrDetails<'a> {file: STR<'a>, line: u32, function: String}
-    
-    #[allow(unused)]
-    pub mod fail {
+                return serde_json::to_string(&self.report()).unwrap_or_else(|err| format!("{{\"message\":\"Fail::to_json - {}\"}}", err));
 
-        use crate::ExtString;
-        use super::*;
-        use core::panic;
-        use std::panic::Location;
-        use std::fmt::Debug;
+            }
 
-        impl<'a> Fail<'a> {
+            /// Appends the report to the error log as a JSON line, if an `IOManager` is installed.
+            pub fn log_report(&self) -> &Self {
 
-            pub fn new(place: &'a Location<'a>, msg: impl Into<STR<'a>>) -> Self {
+                with_logger(|mng| mng.push_report(self));
 
-                return Fail {places: vec![place.as_place()], msg: msg.into()};
+                return self;
 
             }
 
-            #[track_caller] 
-            pub fn panic<T>(msg: impl Into<STR<'a>>) -> T {
+            /// Prints the report on stdout for the parent process to pick up.
+            pub fn print_report(&self) -> &Self {
 
-                <Attempt<'a,T>>::Err(Fail::new(std::panic::Location::caller(), msg)).unwrap();
+                println!("{}", self.to_json());
 
-                unreachable!("tools::panic - Unreachable code reached.");
+                return self;
 
             }
 
-            pub fn from_debug(place: &'a Location<'a>, err: impl Debug) -> Self {
+        }
 
-                return Fail {places: vec![place.as_place()], msg: format!("{:?}", err).into()};
+        impl Fail<'static> {
 
-            }
+            /// Reads a failure back from a line written by `Fail::to_json`.
+            pub fn from_json<'a>(line: &str) -> Attempt<'a,Self> {
 
-            pub fn show(&self) -> String {
+                let report: Report = serde_json::from_str(line.trim()).map_err(|err| {
 
-                let len = self.places.len();
+                    Fail::from(format!("Fail::from_json - Not a failure report: {}", err)).with_category(Category::Parse)
 
-                if len == 0 {panic!("Fail::show - No places in Fail struct");}
+                })?;
 
-                let detail = &self.places[0];
+                return Ok(report.into());
 
-                let mut out = format!("\n\tError: {}", self.msg);
+            }
 
-                for i in 0..len {
-                    
-                    out += &format!("\n\t{} - {}, {}, {}\n", 
-                
-                        i, self.places[i].file, self.places[i].line, self.places[i].function
+        }
 
-                    );
-                }
+        impl From<Report> for Fail<'static> {
+
+            fn from(report: Report) -> Self {
+
+                let places = report.chain.into_iter().map(|frame| ErrDetails {
+
+                    file: frame.file.into(),
+                    line: frame.line,
+                    column: frame.column,
+                    function: frame.function,
+                    note: frame.context.map(Into::into),
 
-                return out.send_to_stderr();
+                }).collect();
+
+                return Fail {
+                    places,
+                    msg: report.message.into(),
+                    cause: report.cause.map(|cause| Box::new(Fail::from(*cause)) as Box<dyn std::error::Error + Send + Sync>),
+                    category: report.category,
+                    code: report.code.map(Into::into),
+                    time: report.timestamp,
+                    trace: Trace::resolved(report.backtrace),
+                };
 
             }
-    
+
         }
 
-        impl std::fmt::Debug for Fail<'_> {fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {write!(f, "{}", self.show())}}
-        impl std::fmt::Display for Fail<'_> {fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {write!(f, "{}", self.show())}}
+        // Other errors have no places, they take the category and time of the failure they caused.
+        fn foreign(err: &(dyn std::error::Error + 'static), category: Category, time: u64) -> Report {
+
+            return Report {
+                message: err.to_string(),
+                category,
+                code: None,
+                timestamp: time,
+                chain: Vec::new(),
+                backtrace: Vec::new(),
+                cause: err.source().map(|source| Box::new(foreign(source, category, time))),
+            };
 
-        impl<'a> From<&'a str> for Fail<'a> {#[track_caller] fn from(msg: &'a str) -> Self {Fail::new(Location::caller(), msg)}}
-        impl From<String> for Fail<'_> {#[track_caller] fn from(msg: String) -> Self {Fail::new(Location::caller(), msg)}}
+        }
 
-        impl From<std::net::AddrParseError> for Fail<'_> {#[track_caller] fn from(err: std::net::AddrParseError) -> Self {Fail::from_debug(Location::caller(), err)}}
+        #[cfg(test)]
+        mod tests {
 
-        impl From<std::io::Error> for Fail<'_> {#[track_caller] fn from(err: std::io::Error) -> Self {Fail::from_debug(Location::caller(), err)}}
+            use super::*;
+            use crate::tools::{fail, Context, Classify};
 
-        impl From<serde_json::Error> for Fail<'_> {#[track_caller] fn from(err: serde_json::Error) -> Self {Fail::from_debug(Location::caller(), err)}}
+            #[test]
+            fn reports_round_trip() {
 
-        impl From<std::string::FromUtf8Error> for Fail<'_> {#[track_caller] fn from(err: std::string::FromUtf8Error) -> Self {Fail::from_debug(Location::caller(), err)}}
+                let inner = fail::<()>("disk gone").category(Category::Io).code("io.disk").unwrap_err().into_owned();
 
-    }
+                let outer = fail::<()>("load failed")
+                    .category(Category::Config)
+                    .code("config.missing")
+                    .context("while loading project.json")
+                    .unwrap_err()
+                    .with_cause(inner);
 
-    mod caller {
+                let line = outer.to_json();
+                let back = Fail::from_json(&line).unwrap();
 
-        use super::{ExtLocation, ErrDetails, STR};
+                assert_eq!(back.to_json(), line);
 
-        use std::collections::HashMap;
-        use std::cell::RefCell;
-        use std::panic::Location;
-        use Type::*;
+                let report = back.report();
 
-        const SRC: [(&str,&str); 2] = [
-            ("src/tools.rs", include_str!("tools.rs")),
-            ("src/main.rs", include_str!("main.rs")),
-        ];
+                assert_eq!((report.message.as_str(), report.category, report.code.as_deref()), ("load failed", Category::Config, Some("config.missing")));
+                assert_eq!(report.chain.len(), 2);
+                assert_eq!(report.chain[1].context.as_deref(), Some("while loading project.json"));
+                assert!(report.chain[0].function.ends_with("reports_round_trip"), "{}", report.chain[0].function);
 
-        // The path is the file name
-        // The map is a hashmap of line numbers to function names and their kind
-        
-        
-        enum Type {Fn, Impl, Trait, Mod}
-        struct CallMap<'a> {map: HashMap<u32, (&'a str, Type)>}
+                let cause = back.caused_by().unwrap();
 
-        thread_local! {static FILE_CACHE: RefCell<HashMap<STR<'static>, CallMap<'static>>> = RefCell::new(HashMap::new());}
+                assert_eq!((cause.msg.as_ref(), cause.category(), cause.code()), ("disk gone", Category::Io, Some("io.disk")));
 
-        impl ExtLocation for Location<'_> {
-            
-            fn as_place<'a>(&'a self) -> ErrDetails<'a> {
+            }
 
-                let file: STR = self.file().to_string().into();
-                let line: u32 = self.line();
-                let mut function = String::new();
+            #[test]
+            fn foreign_causes_keep_their_message() {
 
-                FILE_CACHE.with(|cache| {
-    
-                    let mut cache = cache.borrow_mut();
-        
-                    let map: &CallMap<'_> = match cache.get(file.as_ref()) {
-        
-                        Some(file) => file,
-        
-                        None => {
+                let err = Fail::from(std::io::Error::new(std::io::ErrorKind::NotFound, "no such file"));
 
-                            let src = SRC.iter()
-                                .find(|(name, _)| *name == file)
-                                .map(|(_, src)| src)
-                                .expect(&format!("caller::Location::as_place - Failed to find file: {}", &file));
-        
-                            cache.insert(file.clone(), CallMap::new(src));
+                let cause = err.report().cause.unwrap();
 
-                            cache.get(&file)
-                                .expect(&format!("caller::Location::as_place - Failed to get inserted file back out. File: {}", &file))
+                assert_eq!((cause.message.as_str(), cause.category, cause.chain.len()), ("no such file", Category::Io, 0));
 
-                        }
-        
-                    };
-        
-                    let mut l: u32 = line;
-                    let mut func_name: Option<&str> = None;
-                    let mut impl_trait_name: Option<&str> = None;
-                    let mut mod_name: Option<&str> = None;
-    
-                    while let Some((name, kind)) = next(&map, &mut l) {
-    
-                        if l > 0 {l -= 1;} else {break;}
+                let back = Fail::from_json(&err.to_json()).unwrap();
 
-                        if matches!(kind, Fn) && func_name.is_none() {func_name = Some(name); continue;}
+                assert_eq!(back.caused_by().unwrap().msg, "no such file");
+                assert!(back.show().contains("Caused by:"));
 
-                        if (matches!(kind, Trait) || matches!(kind, Impl)) && impl_trait_name.is_none() {
-                            
-                            impl_trait_name = Some(name); continue;
-                        
-                        }
+            }
 
-                        if matches!(kind, Mod) {mod_name = Some(name); break;}
-    
-                    }
-    
-                    if let Some(mod_name) = mod_name {function += &format!("{}::", mod_name);}
-    
-                    if let Some(impl_trait_name) = impl_trait_name {function += &format!("{}::", impl_trait_name);}
-    
-                    if let Some(total) = func_name {function += total;}
-    
-                });
+            #[test]
+            fn other_json_is_rejected() {
+
+                let err = Fail::from_json("{\"message\": 1}").unwrap_err();
+
+                assert_eq!(err.category(), Category::Parse);
 
-                return ErrDetails {file, line, function};
-    
             }
-        
-        }
 
-        fn next<'a>(map: &'a CallMap, i: &mut u32) -> Option<&'a (&'a str, Type)> {
-    
-            while map.get(&i).is_none() && *i > 0 {*i -= 1;} return map.get(&i);
-        
         }
-        
-        mod caller_mappings {
 
-            use super::{CallMap, Type, Type::*};
-            use std::collections::HashMap;
+    }
 
-            impl<'a> CallMap<'a> {
+    mod category {
 
-                pub fn new(src: &'a str) -> Self {
-    
-                    let mut map: HashMap<u32, (&str, Type)> = HashMap::new();
-    
-                    for (i, line) in src.split('\n').enumerate() {
-    
-                        let num = i as u32 + 1; // Line numbers start at 1
-            
-                        if let Some((_, closest)) = Type::find_closest(&line) {
-            
-                            match closest {
+        use super::Category;
 
-                                Fn => {extract_fn(&line).map(|s| map.insert(num, (s, Type::Fn)));},
-                                Impl => {extract_impl(&line).map(|s| map.insert(num, (s, Type::Impl)));},
-                                Trait => {extract_trait(&line).map(|s| map.insert(num, (s, Type::Trait)));},
-                                Mod => {extract_mod(&line).map(|s| map.insert(num, (s, Type::Mod)));},
-            
-                            }
-                        }
-    
-                    }
-    
-                    return CallMap {map};
-    
-                }
-    
-            }
+        impl Category {
 
-            impl<'a> std::ops::Deref for CallMap<'a> {
-                
-                type Target = HashMap<u32, (&'a str, Type)>; 
-                
-                fn deref(&self) -> &HashMap<u32, (&'a str, Type)> {&self.map}
-            
-            }
+            /// The process exit status for a failure of this category, following BSD `sysexits.h`:
+            /// config 78 (EX_CONFIG), io 74 (EX_IOERR), network 69 (EX_UNAVAILABLE), parse 65 (EX_DATAERR),
+            /// python 76 (EX_PROTOCOL) and internal 70 (EX_SOFTWARE).
+            pub const fn exit_code(&self) -> i32 {
 
-            fn extract_impl(line: &str) -> Option<&str> {
+                match self {
 
-                if !(line.contains(Impl.as_str()) && line.contains("{")) {return None;}
-        
-                let line = line.split(Impl.as_str()).nth(1)?;
-        
-                let start = line.find(">")
-                    .or_else(|| Some(0))?;
-        
-                let end = closet_str(&line, ["for", "{"])?.0;
-        
-                if start >= end || end >= line.len() {return None;}
-        
-                Some(line[start..end].trim())
-        
-            }
-        
-            fn extract_trait(line: &str) -> Option<&str> {
-        
-                if !(line.contains(Trait.as_str()) && line.contains("{")) {return None;}
-        
-                let line = line.split(Trait.as_str()).nth(1)?;
+                    Category::Config => 78,
+                    Category::Io => 74,
+                    Category::Network => 69,
+                    Category::Parse => 65,
+                    Category::Python => 76,
+                    Category::Internal => 70,
 
-                let end = closet_str(&line, ["<", "{", ":"])?.0;
-        
-                Some(line[..end].trim())
-        
-            }
-            
-            fn extract_mod(line: &str) -> Option<&str> {
-        
-                if !line.contains(Mod.as_str()) {return None;}
-        
-                let line = line.split(Mod.as_str()).nth(1)?;
-        
-                let end = line.find("{")?;
-        
-                Some(line[..end].trim())
-        
-            }
-        
-            fn extract_fn(line: &str) -> Option<&str> {
-        
-                if !line.contains(Fn.as_str()) {return None;}
-        
-                let line = line.split(Fn.as_str()).nth(1)?;
+                }
 
-                let end = closet_str(line, ["<", "("])?.0;
-            
-                Some(line[..end].trim())
-            
             }
 
-            fn closet_str<'a,const N: usize>(line: &'a str, ary: [&'a str; N]) -> Option<(usize, &'a str)> {
+            pub const fn as_str(&self) -> &'static str {
+
+                match self {
+
+                    Category::Config => "config",
+                    Category::Io => "io",
+                    Category::Network => "network",
+                    Category::Parse => "parse",
+                    Category::Python => "python",
+                    Category::Internal => "internal",
 
-                let mut best: Option<(usize, &str)> = None;
-        
-                for string in ary.iter() {
-        
-                    match (best, line.find(string)) {
-        
-                        (Some((min, _)), Some(val)) => {
-        
-                            if val < min {best = Some((val, string))}
-                        
-                        },
-        
-                        (None, Some(val)) => {best = Some((val, string))},
-        
-                        (_, _) => {},
-        
-                    }
                 }
-        
-                return best;
-            
+
             }
-            
+
         }
 
-        mod code_types {
+        impl std::fmt::Display for Category {fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {write!(f, "{}", self.as_str())}}
 
-            use super::{Type, Type::*};
+    }
 
-            const FN: &str = "fn ";
-            const IMPL: &str = "impl ";
-            const TRAIT: &str = "trait ";
-            const MOD: &str = "mod ";
+    mod extend {
 
-            const ORDERED: [Type; 4] = [Fn, Impl, Trait, Mod];
+        use super::*;
+        use crate::tools::{MapOption, ExtResult, Context, IntoOwned, Classify};
+        use std::panic::Location;
 
-            impl Type {
+        impl<'a,T> ExtResult<T> for Attempt<'a,T> {
 
-                pub const fn as_str<'a>(&self) -> &'a str {
+            fn unwrap_or_stderr(self) -> T {
 
-                    match self {
+                self.unwrap_or_else(|e| {e.emit(); std::process::exit(e.category.exit_code());})
 
-                        Type::Fn => &FN,
-                        Type::Impl => &IMPL,
-                        Type::Trait => &TRAIT,
-                        Type::Mod => &MOD,
+            }
 
-                    }
+        }
 
-                }
+        impl<'a,T> Context<'a,T> for Attempt<'a,T> {
 
-                pub fn find_closest<'a>(line: &'a str) -> Option<(usize, Type)> {
+            #[track_caller]
+            fn context(self, msg: impl Into<STR<'a>>) -> Attempt<'a,T> {
 
-                    let mut best: Option<(usize, Type)> = None;
-            
-                    for ftype in ORDERED.iter() {
-            
-                        match (best, line.find(ftype.as_str())) {
-            
-                            (Some((min, _)), Some(val)) => {
-            
-                                if val < min {best = Some((val, *ftype))}
-                            
-                            },
-            
-                            (None, Some(val)) => {best = Some((val, *ftype))},
-            
-                            (_, _) => {},
-            
-                        }
-                    }
-            
-                    return best;
-                
-                }
+                let place = Location::caller();
+
+                return self.map_err(|err| err.context(place, msg));
 
             }
 
-            impl Copy for Type {}
-            impl Clone for Type {fn clone(&self) -> Self {return *self;}}
+            #[track_caller]
+            fn with_context<M: Into<STR<'a>>>(self, msg: impl FnOnce() -> M) -> Attempt<'a,T> {
 
-            impl std::ops::Deref for Type {type Target = str; fn deref(&self) -> &str {self.as_str()}}
+                let place = Location::caller();
+
+                return self.map_err(|err| err.context(place, msg()));
+
+            }
 
         }
 
-    }
+        impl<'a,T> IntoOwned<T> for Attempt<'a,T> {
 
-    mod extend {
+            fn into_owned(self) -> Attempt<'static,T> {return self.map_err(Fail::into_owned);}
 
-        use super::*;
-        use crate::tools::{MapOption, ExtResult, ExtString};
+        }
 
-        impl<'a,T> ExtResult<T> for Attempt<'a,T> {
+        impl<'a,T> Classify<'a,T> for Attempt<'a,T> {
 
-            fn unwrap_or_stderr(self) -> T {
+            fn category(self, category: Category) -> Attempt<'a,T> {return self.map_err(|err| err.with_category(category));}
+
+            fn code(self, code: &'static str) -> Attempt<'a,T> {return self.map_err(|err| err.with_code(code));}
+
+        }
 
-                self.unwrap_or_else(|e| {e.show().send_to_stderr(); std::process::exit(1);})
+        impl<'a,T> MapOption<'a,T> for Option<T> {
+
+            #[track_caller]
+            fn attempt(self, msg: impl Into<STR<'a>>) -> Attempt<'a,T> {
+
+                match self {Some(val) => Ok(val), None => Err(Fail::new(std::panic::Location::caller(), msg))}
 
             }
 
         }
 
-        impl<'a,T> MapOption<'a,T> for Option<T> {}
-
     }
 
 }
@@ -569,41 +4623,58 @@ mod connection {
 
     use crate::tools::*;
     use std::net::{SocketAddr, TcpStream, IpAddr};
+    use std::time::Duration;
 
     impl Connection {
 
-        pub fn new<'a>(ip: &'a str, port: u16, timeout: u64) -> Attempt<'a,Self> {
+        pub fn new(ip: &str, port: u16, timeout: Duration) -> Attempt<'static,Self> {
         
             use rayon::prelude::{IntoParallelIterator, ParallelIterator};
         
             const BASE_IP: &str = "192.168.1.";
             const MAX_IP: u8 = 254;
         
-            // Try to connect to the given IP address
-            if let Ok(stream) = try_connect(ip, port, timeout) {return Ok(Connection {stream});}
+            // Try to connect to the given IP address, its failure is kept as the cause if the LAN search fails too
+            let direct = match try_connect(ip, port, timeout) {
+
+                Ok(stream) => return Ok(Connection {stream}),
+                Err(err) => err,
+
+            };
         
             // Search all possible IP addresses in the LAN
             let tcp = (1..=MAX_IP).into_par_iter()
                 .map(|i| {
         
-                    try_connect(format!("{}{}", BASE_IP, i), port, timeout)
+                    without_backtrace(|| try_connect(format!("{}{}", BASE_IP, i), port, timeout))
         
                 })
                 .find_any(|stream| stream.is_ok())
                 .unwrap_or_else(|| {
                     
                     fail(format!("No reachable server found for LAN at address: 192.168.1.X:{}", port))
+                        .category(Category::Network)
+                        .code("network.unreachable")
+                        .map_err(|err| err.with_cause(direct))
                 
                 })?;
 
             return Ok(Connection {stream: tcp});
         
-            fn try_connect<'a>(ip: impl AsRef<str>, port: u16, timeout: u64) -> Attempt<'a,TcpStream> {
+            // `Fail::from` records its caller, passed as a bare fn path that would be inside core
+            #[allow(clippy::redundant_closure)]
+            fn try_connect(ip: impl AsRef<str>, port: u16, timeout: Duration) -> Attempt<'static,TcpStream> {
         
-                return Ok(TcpStream::connect_timeout(
-                    &SocketAddr::new(ip.as_ref().parse::<IpAddr>()?, port),
-                    std::time::Duration::from_millis(timeout)
-                )?);
+                let ip = ip.as_ref();
+
+                let addr = ip.parse::<IpAddr>()
+                    .map_err(|err| Fail::from(err))
+                    .with_context(|| format!("while parsing peer address '{}'", ip))?;
+
+                return TcpStream::connect_timeout(&SocketAddr::new(addr, port), timeout)
+                    .map_err(|err| Fail::from(err))
+                    .category(Category::Network)
+                    .with_context(|| format!("while connecting to peer {}:{}", ip, port));
         
             }
         
@@ -617,7 +4688,7 @@ mod connection {
 
 mod io_manager {
 
-    use super::{IOManager, Attempt, ExtString};
+    use super::{IOManager, ExtString, Fail, Category, LOGGER, redact};
     use std::fs::OpenOptions;
     use std::path::Path;
     use fs2::FileExt;
@@ -641,7 +4712,7 @@ mod io_manager {
 
                     println!("IOManager::new - Failed to access Error Log. {:?}", err);
 
-                    std::process::exit(1);
+                    std::process::exit(Category::Io.exit_code());
 
                 });
 
@@ -654,7 +4725,7 @@ mod io_manager {
 
                     println!("IOManager::new - Failed to access Output Log. {:?}", err);
 
-                    std::process::exit(1);
+                    std::process::exit(Category::Io.exit_code());
 
                 });
 
@@ -665,6 +4736,14 @@ mod io_manager {
         
         }
 
+        /// Makes this the log for every thread, used by `send_to_stdout`/`send_to_stderr`, `LogReporter`
+        /// and the panic hook.
+        pub fn install(self) {
+
+            *LOGGER.lock().unwrap_or_else(|poison| poison.into_inner()) = Some(self);
+
+        }
+
         fn format_log<'a>(content: &str) -> String {
 
             let timestamp = SystemTime::now()
@@ -672,7 +4751,7 @@ mod io_manager {
                 .unwrap_or_default()
                 .as_secs();
 
-            return format!("\nTime: {}, PID: {}{}", timestamp, process::id(), content);
+            return format!("\nTime: {}, PID: {}{}", timestamp, process::id(), redact(content.into()));
 
         }
 
@@ -683,6 +4762,14 @@ mod io_manager {
 
         }
 
+        /// Writes `fail` to the error log as one JSON line, without the usual time/PID header.
+        pub fn push_report(&mut self, fail: &Fail) {
+
+            writeln!(self.stderr, "{}", fail.to_json()).unwrap();
+            self.stderr.sync_all().unwrap();
+
+        }
+
         pub fn push_stderr<'a,T: ExtString>(&mut self, content: T) -> T {
 
             writeln!(self.stderr, "{}", Self::format_log(content.as_ref())).unwrap();